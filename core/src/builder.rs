@@ -0,0 +1,288 @@
+//! Module containing builders for creating (validated) `Mail` instances.
+use soft_ascii_string::SoftAsciiString;
+use vec1::Vec1;
+
+use headers::{
+    Header, HeaderKind,
+    HeaderMap,
+    headers::{ContentType, ContentTransferEncoding},
+    header_components::MediaType
+};
+
+use ::{
+    error::{MailError, OtherValidationError},
+    mail::Mail,
+    resource::Resource
+};
+
+/// Entry point for building a `Mail` using a builder.
+///
+/// Depending on wether a singlepart or multipart mail is build
+/// either a `SinglepartBuilder` or a `MultipartBuilder` is returned.
+///
+/// In difference to creating a mail with e.g. `Mail::new_singlepart_mail`
+/// and inserting the headers by hand the builders check the headers when
+/// they are added and the `Mail` returned by `build` is validated.
+#[derive(Debug)]
+pub struct Builder;
+
+impl Builder {
+
+    /// Starts building a singlepart mail with given resource as body.
+    pub fn singlepart(body: Resource) -> SinglepartBuilder {
+        SinglepartBuilder {
+            inner: Mail::new_singlepart_mail(body)
+        }
+    }
+
+    /// Starts building a multipart mail with given (multipart) media type.
+    ///
+    /// The media type is not required to have a boundary parameter
+    /// as it is auto generated when turning the mail into an encodable
+    /// mail.
+    ///
+    /// # Error
+    ///
+    /// Fails with `OtherValidationError::SingleMultipartMixup` if the
+    /// media type is not a `multipart` media type.
+    pub fn multipart(media_type: MediaType)
+        -> Result<MultipartBuilder, OtherValidationError>
+    {
+        if !media_type.is_multipart() {
+            return Err(OtherValidationError::SingleMultipartMixup);
+        }
+
+        Ok(MultipartBuilder {
+            media_type,
+            headers: HeaderMap::new(),
+            bodies: Vec::new(),
+            preamble: SoftAsciiString::new(),
+            epilogue: SoftAsciiString::new()
+        })
+    }
+}
+
+/// A builder for a mail with a singlepart body.
+#[derive(Debug)]
+pub struct SinglepartBuilder {
+    inner: Mail
+}
+
+impl SinglepartBuilder {
+
+    /// Adds a header to the mail.
+    ///
+    /// # Error
+    ///
+    /// Fails if a `Content-Type` header (which is derived from the body
+    /// for singlepart mails) or a `Content-Transfer-Encoding` header
+    /// (which is always auto-generated) is given.
+    pub fn header<H>(self, header: Header<H>) -> Result<Self, MailError>
+        where H: HeaderKind
+    {
+        let mut headers = HeaderMap::new();
+        headers.insert(header);
+        self.headers(headers)
+    }
+
+    /// Adds all headers to the mail.
+    ///
+    /// # Error
+    ///
+    /// Fails in the same cases as `header`.
+    pub fn headers(mut self, headers: HeaderMap) -> Result<Self, MailError> {
+        if headers.contains(ContentType) {
+            return Err(OtherValidationError::InsertSinglepartContentTypeHeader.into());
+        }
+        if headers.contains(ContentTransferEncoding) {
+            return Err(OtherValidationError::ContentTransferEncodingHeaderGiven.into());
+        }
+        self.inner.insert_headers(headers);
+        Ok(self)
+    }
+
+    /// Builds the mail.
+    ///
+    /// # Error
+    ///
+    /// Fails if the mail does not pass `Mail::generally_validate_mail`.
+    pub fn build(self) -> Result<Mail, MailError> {
+        self.inner.generally_validate_mail()?;
+        Ok(self.inner)
+    }
+}
+
+/// A builder for a mail with a multipart body.
+#[derive(Debug)]
+pub struct MultipartBuilder {
+    media_type: MediaType,
+    headers: HeaderMap,
+    bodies: Vec<Mail>,
+    preamble: SoftAsciiString,
+    epilogue: SoftAsciiString
+}
+
+impl MultipartBuilder {
+
+    /// Adds a header to the mail.
+    ///
+    /// # Error
+    ///
+    /// Fails if a `Content-Transfer-Encoding` header (which is always
+    /// auto-generated) or a `Content-Type` header is given. The media
+    /// type of the mail is the one passed to `Builder::multipart`, which
+    /// is not silently replaced by a `Content-Type` header.
+    pub fn header<H>(self, header: Header<H>) -> Result<Self, MailError>
+        where H: HeaderKind
+    {
+        let mut headers = HeaderMap::new();
+        headers.insert(header);
+        self.headers(headers)
+    }
+
+    /// Adds all headers to the mail.
+    ///
+    /// # Error
+    ///
+    /// Fails in the same cases as `header`.
+    pub fn headers(mut self, headers: HeaderMap) -> Result<Self, MailError> {
+        if headers.contains(ContentTransferEncoding) {
+            return Err(OtherValidationError::ContentTransferEncodingHeaderGiven.into());
+        }
+        if headers.contains(ContentType) {
+            return Err(OtherValidationError::MultipartContentTypeHeaderGiven.into());
+        }
+        self.headers.insert_all(headers);
+        Ok(self)
+    }
+
+    /// Adds a body to the multipart mail.
+    pub fn body(mut self, body: Mail) -> Self {
+        self.bodies.push(body);
+        self
+    }
+
+    /// Adds all bodies to the multipart mail.
+    pub fn bodies(mut self, bodies: impl IntoIterator<Item=Mail>) -> Self {
+        self.bodies.extend(bodies);
+        self
+    }
+
+    /// Sets the multipart preamble, see `Mail::set_preamble`.
+    pub fn preamble(mut self, preamble: SoftAsciiString) -> Self {
+        self.preamble = preamble;
+        self
+    }
+
+    /// Sets the multipart epilogue, see `Mail::set_epilogue`.
+    pub fn epilogue(mut self, epilogue: SoftAsciiString) -> Self {
+        self.epilogue = epilogue;
+        self
+    }
+
+    /// Builds the mail.
+    ///
+    /// # Error
+    ///
+    /// Fails with `OtherValidationError::EmptyMultipartBody` if no body
+    /// was added or if the mail does not pass `Mail::generally_validate_mail`.
+    pub fn build(self) -> Result<Mail, MailError> {
+        let MultipartBuilder { media_type, headers, bodies, preamble, epilogue } = self;
+        let bodies = Vec1::try_from_vec(bodies)
+            .map_err(|_| OtherValidationError::EmptyMultipartBody)?;
+
+        let mut mail = Mail::new_multipart_mail(media_type, bodies);
+        mail.insert_headers(headers);
+        mail.set_preamble(preamble)?;
+        mail.set_epilogue(epilogue)?;
+        mail.generally_validate_mail()?;
+        Ok(mail)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use headers::{
+        headers::{_From, Subject, ContentType, ContentTransferEncoding},
+        header_components::{MediaType, TransferEncoding}
+    };
+    use headers::error::HeaderValidationError;
+    use default_impl::test_context;
+    use ::mail::MailBody;
+    use super::*;
+
+    fn resource() -> Resource {
+        Resource::plain_text("r0", &test_context())
+    }
+
+    test!(builds_singlepart_mail, {
+        let mail = Builder::singlepart(resource())
+            .header(Subject::auto_body("hy there")?)?
+            .headers(headers! {
+                _From: ["random@this.is.no.mail"]
+            }?)?
+            .build()?;
+
+        assert!(mail.headers().contains(Subject));
+        assert!(mail.headers().contains(_From));
+        assert_not!(mail.has_multipart_body());
+    });
+
+    test!(singlepart_rejects_content_type, {
+        let media_type = MediaType::parse("text/plain").unwrap();
+        assert_err!(Builder::singlepart(resource()).header(ContentType::body(media_type)));
+    });
+
+    test!(singlepart_rejects_content_transfer_encoding, {
+        let header = ContentTransferEncoding::body(TransferEncoding::Base64);
+        assert_err!(Builder::singlepart(resource()).header(header));
+    });
+
+    test!(builds_multipart_mail, {
+        let media_type = MediaType::parse("multipart/mixed").unwrap();
+        let mail = Builder::multipart(media_type)?
+            .body(Mail::new_singlepart_mail(resource()))
+            .bodies(vec![Mail::new_singlepart_mail(resource())])
+            .preamble(SoftAsciiString::from_unchecked("preamble"))
+            .build()?;
+
+        match mail.body() {
+            &MailBody::MultipleBodies { ref bodies, ref hidden_text, .. } => {
+                assert_eq!(bodies.len(), 2);
+                assert_eq!(hidden_text.as_str(), "preamble");
+            },
+            _ => panic!("expected multipart body")
+        }
+    });
+
+    #[test]
+    fn multipart_rejects_non_multipart_media_type() {
+        let media_type = MediaType::parse("text/plain").unwrap();
+        assert_err!(Builder::multipart(media_type));
+    }
+
+    test!(multipart_rejects_non_multipart_content_type_header, {
+        let media_type = MediaType::parse("multipart/mixed").unwrap();
+        let content_type = ContentType::body(MediaType::parse("text/plain").unwrap());
+        assert_err!(Builder::multipart(media_type)?.header(content_type));
+    });
+
+    test!(multipart_rejects_content_type_header, {
+        let media_type = MediaType::parse("multipart/mixed").unwrap();
+        let content_type = ContentType::body(MediaType::parse("multipart/alternative").unwrap());
+        match assert_err!(Builder::multipart(media_type)?.header(content_type)) {
+            MailError::Validation(HeaderValidationError::Custom(ref err)) => {
+                match err.downcast_ref::<OtherValidationError>() {
+                    Some(&OtherValidationError::MultipartContentTypeHeaderGiven) => {},
+                    other => panic!("unexpected error: {:?}", other)
+                }
+            },
+            other => panic!("unexpected error: {:?}", other)
+        }
+    });
+
+    test!(multipart_rejects_empty_body, {
+        let media_type = MediaType::parse("multipart/mixed").unwrap();
+        assert_err!(Builder::multipart(media_type)?.build());
+    });
+}
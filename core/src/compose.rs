@@ -19,6 +19,7 @@
 
 use media_type::{MULTIPART, ALTERNATIVE, RELATED, MIXED};
 use vec1::Vec1;
+use soft_ascii_string::SoftAsciiString;
 
 #[cfg(feature="serde")]
 use serde::{Serialize, Deserialize};
@@ -37,6 +38,7 @@ use headers::{
 use ::mail::Mail;
 use ::context::Context;
 use ::resource::Resource;
+use ::error::OtherValidationError;
 
 
 /// Parts used to create a mail body (in a multipart mail).
@@ -190,6 +192,33 @@ impl Embedded {
 
 impl MailParts {
 
+    /// Unloads all resources which can be unloaded.
+    ///
+    /// See `Mail::unload_resources`, this returns the number of
+    /// resources which had been unloaded.
+    pub fn unload_resources(&mut self) -> usize {
+        let mut unloaded = 0;
+        {
+            let mut unload = |resource: &mut Resource| {
+                if !resource.is_source() && resource.unload().is_ok() {
+                    unloaded += 1;
+                }
+            };
+
+            for body in self.alternative_bodies.iter_mut() {
+                unload(&mut body.resource);
+                for embedding in body.embeddings.iter_mut() {
+                    unload(embedding.resource_mut());
+                }
+            }
+
+            for embedding in self.embeddings.iter_mut() {
+                unload(embedding.resource_mut());
+            }
+        }
+        unloaded
+    }
+
     /// Generating content ids for all contained `Embedded` instances which don't have a cid.
     ///
     pub fn generate_content_ids(&mut self, ctx: &impl Context) {
@@ -383,7 +412,8 @@ impl Mail {
     {
         let mut bodies = other_bodies;
         bodies.push(self);
-        new_multipart(&MIXED, bodies)
+        //UNWRAP_SAFE: we just pushed a body
+        new_multipart(&MIXED, Vec1::try_from_vec(bodies).unwrap())
     }
 
     /// Create a `multipart/alternative` `Mail` instance containing this
@@ -400,7 +430,8 @@ impl Mail {
     {
         let mut bodies = alternates;
         bodies.insert(0, self);
-        new_multipart(&ALTERNATIVE, bodies)
+        //UNWRAP_SAFE: we just inserted a body
+        new_multipart(&ALTERNATIVE, Vec1::try_from_vec(bodies).unwrap())
     }
 
     /// Creates a `multipart/related` `Mail` instance containing this
@@ -410,7 +441,41 @@ impl Mail {
     {
         let mut bodies = related;
         bodies.insert(0, self);
-        new_multipart(&RELATED, bodies)
+        //UNWRAP_SAFE: we just inserted a body
+        new_multipart(&RELATED, Vec1::try_from_vec(bodies).unwrap())
+    }
+
+    /// Sets the multipart preamble and returns the mail.
+    ///
+    /// This is meant to be chained with the `wrap_with_*` methods, e.g.
+    /// `mail.wrap_with_mixed(attachments).with_preamble(text)`.
+    /// See `Mail::set_preamble` for more details.
+    ///
+    /// # Error
+    ///
+    /// Fails with `OtherValidationError::NotMultipart` if the mail does
+    /// not have a multipart body.
+    pub fn with_preamble(mut self, preamble: SoftAsciiString)
+        -> Result<Mail, OtherValidationError>
+    {
+        self.set_preamble(preamble)?;
+        Ok(self)
+    }
+
+    /// Sets the multipart epilogue and returns the mail.
+    ///
+    /// This is meant to be chained with the `wrap_with_*` methods.
+    /// See `Mail::set_epilogue` for more details.
+    ///
+    /// # Error
+    ///
+    /// Fails with `OtherValidationError::NotMultipart` if the mail does
+    /// not have a multipart body.
+    pub fn with_epilogue(mut self, epilogue: SoftAsciiString)
+        -> Result<Mail, OtherValidationError>
+    {
+        self.set_epilogue(epilogue)?;
+        Ok(self)
     }
 
 }
@@ -421,7 +486,7 @@ impl Mail {
 ///
 /// If `sub_type` can not be used to create a multipart content
 /// type this will panic.
-fn new_multipart(sub_type: &'static str, bodies: Vec<Mail>)
+fn new_multipart(sub_type: &'static str, bodies: Vec1<Mail>)
     -> Mail
 {
     let content_type = MediaType::new(MULTIPART, sub_type)
@@ -1,6 +1,8 @@
 //! Provides the context needed for building/encoding mails.
+use std::io;
 use std::sync::Arc;
 use std::fmt::Debug;
+use std::time::Duration;
 
 use futures::{ future, Future, IntoFuture };
 use utils::SendBoxFuture;
@@ -11,6 +13,7 @@ use headers::header_components::{
 
 use ::error::ResourceLoadingError;
 use ::resource::{Source, Data, EncData};
+use ::iri::IRI;
 
 /// This library needs a context for creating/encoding mails.
 ///
@@ -71,7 +74,8 @@ pub trait Context: Debug + Clone + Send + Sync + 'static {
     /// a encodable mail an a `Resource::Data` instance is found.
     ///
     /// The default impl. of this function just calls
-    /// `data.transfer_encode(data, Default::default())` but a more
+    /// `data.transfer_encode(data.transfer_encoding_hint())`, i.e. it
+    /// respects the hint stored in the datas `Metadata`, but a more
     /// sophisticated implementation could use the `Data`s content id
     /// for some caching scheme e.g. a LRU cache. Which can safe the
     /// encoding step for commonly used resources like e.g. a logo.
@@ -85,9 +89,28 @@ pub trait Context: Debug + Clone + Send + Sync + 'static {
         -> SendBoxFuture<EncData, ResourceLoadingError>
     {
         let data = data.clone();
-        self.offload_fn(move || Ok(data.transfer_encode(Default::default())))
+        self.offload_fn(move || Ok(data.transfer_encode(data.transfer_encoding_hint())))
     }
 
+    /// Signals that resources "inside" of `preload` are going to be loaded.
+    ///
+    /// E.g. if `preload` points to an archive a resource loader can
+    /// open it once and keep it open until `stop_using_preload` is
+    /// called instead of opening it for every resource loaded from it.
+    /// Calls to `use_preload` and `stop_using_preload` are paired, but
+    /// multiple (e.g. mails) can use the same preload concurrently.
+    ///
+    /// This function should not block, e.g. by lazily opening an
+    /// archive when the first resource is loaded from it.
+    ///
+    /// The default implementation does nothing.
+    fn use_preload(&self, _preload: &IRI) {}
+
+    /// Signals that the preload is no longer used by the caller.
+    ///
+    /// See `use_preload`, the default implementation does nothing.
+    fn stop_using_preload(&self, _preload: &IRI) {}
+
     /// generate a unique content id
     ///
     /// As message id's are used to reference messages they should be
@@ -118,10 +141,14 @@ pub trait Context: Debug + Clone + Send + Sync + 'static {
 
     //TODO[futures/v>=0.2]: integrate this with Context
     /// offloads the execution of the future `fut` to somewhere else e.g. a cpu pool
+    ///
+    /// If the offloading itself fails (e.g. because the runtime the future was
+    /// spawned on was shut down) the returned future fails with an `io::Error`
+    /// converted into the error type of the future.
     fn offload<F>(&self, fut: F) -> SendBoxFuture<F::Item, F::Error>
         where F: Future + Send + 'static,
               F::Item: Send + 'static,
-              F::Error: Send + 'static;
+              F::Error: From<io::Error> + Send + 'static;
 
     //TODO[futures/v>=0.2]: integrate this with Context
     /// offloads the execution of the function `func` to somewhere else e.g. a cpu pool
@@ -130,10 +157,67 @@ pub trait Context: Debug + Clone + Send + Sync + 'static {
               I: IntoFuture + 'static,
               I::Future: Send + 'static,
               I::Item: Send + 'static,
-              I::Error: Send + 'static
+              I::Error: From<io::Error> + Send + 'static
     {
         self.offload( future::lazy( func ) )
     }
+
+    /// offloads the execution of the blocking function `func` to somewhere else
+    ///
+    /// Unlike `offload_fn` this is meant for functions which block on IO
+    /// (e.g. reading a file) instead of doing CPU heavy work (e.g. transfer
+    /// encoding). This allows implementations to run them on a different
+    /// (e.g. larger) thread pool then the CPU heavy work.
+    ///
+    /// The default implementation just calls `offload_fn`.
+    fn offload_blocking_fn<FN, T, E>(&self, func: FN) -> SendBoxFuture<T, E>
+        where FN: FnOnce() -> Result<T, E> + Send + 'static,
+              T: Send + 'static,
+              E: From<io::Error> + Send + 'static
+    {
+        self.offload_fn(func)
+    }
+
+    /// Returns the limits used when loading the resources of a mail.
+    ///
+    /// The default implementation returns `LoadingLimits::default()`,
+    /// i.e. no limits at all.
+    fn loading_limits(&self) -> LoadingLimits {
+        Default::default()
+    }
+}
+
+/// Limits used by `MailFuture` when loading the resources of a mail.
+///
+/// If a timeout expires loading the affected resources fails with
+/// `ResourceLoadingErrorKind::Timeout`. If the timer itself fails the
+/// affected resource (`resource_timeout`) or the whole `MailFuture`
+/// (`overall_timeout`) fails with `LoadingFailed`, with the timer error
+/// as cause.
+///
+/// The timeouts require the (opt-in) `loading_timeouts` feature, without
+/// it they are ignored (and a warning is logged).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct LoadingLimits {
+    /// The maximal number of resources of a mail loaded concurrently.
+    ///
+    /// Further resources are loaded once a load completes. If `None`
+    /// all resources are loaded at once. A limit of `0` is treated
+    /// like a limit of `1`.
+    pub max_in_flight: Option<usize>,
+
+    /// The maximal time loading a single resource can take.
+    ///
+    /// The time is measured from the point the loading of the resource
+    /// is started (i.e. time spent waiting because of `max_in_flight`
+    /// is not included).
+    pub resource_timeout: Option<Duration>,
+
+    /// The maximal time loading all resources of a mail can take.
+    ///
+    /// The time is measured from the point the `MailFuture` is polled
+    /// the first time.
+    pub overall_timeout: Option<Duration>
 }
 
 
@@ -155,8 +239,18 @@ pub trait ResourceLoaderComponent: Debug + Send + Sync + 'static {
         -> SendBoxFuture<EncData, ResourceLoadingError>
     {
         let data = data.clone();
-        ctx.offload_fn(move || Ok(data.transfer_encode(Default::default())))
+        ctx.offload_fn(move || Ok(data.transfer_encode(data.transfer_encoding_hint())))
     }
+
+    /// Calls to `Context::use_preload` will be forwarded to this method.
+    ///
+    /// The default implementation does nothing.
+    fn use_preload(&self, _preload: &IRI) {}
+
+    /// Calls to `Context::stop_using_preload` will be forwarded to this method.
+    ///
+    /// The default implementation does nothing.
+    fn stop_using_preload(&self, _preload: &IRI) {}
 }
 
 /// Trait needed to be implemented for providing the offloading parts to a `CompositeContext`.
@@ -166,7 +260,19 @@ pub trait OffloaderComponent: Debug + Send + Sync + 'static {
     fn offload<F>(&self, fut: F) -> SendBoxFuture<F::Item, F::Error>
         where F: Future + Send + 'static,
               F::Item: Send+'static,
-              F::Error: Send+'static;
+              F::Error: From<io::Error> + Send + 'static;
+
+    /// Calls to `Context::offload_blocking_fn` will be forwarded to this method.
+    ///
+    /// The default implementation offloads the function like any other
+    /// future using `offload`.
+    fn offload_blocking_fn<FN, T, E>(&self, func: FN) -> SendBoxFuture<T, E>
+        where FN: FnOnce() -> Result<T, E> + Send + 'static,
+              T: Send + 'static,
+              E: From<io::Error> + Send + 'static
+    {
+        self.offload(future::lazy(func))
+    }
 }
 
 /// Trait needed to be implemented for providing the id generation parts to a `CompositeContext`.
@@ -205,6 +311,7 @@ pub struct CompositeContext<
     M: MailIdGenComponent
 >{
     inner: Arc<(R, O, M)>,
+    limits: LoadingLimits
 }
 
 impl<R, O, M> Clone for CompositeContext<R, O, M>
@@ -215,6 +322,7 @@ impl<R, O, M> Clone for CompositeContext<R, O, M>
     fn clone(&self) -> Self {
         CompositeContext {
             inner: self.inner.clone(),
+            limits: self.limits
         }
     }
 }
@@ -228,9 +336,18 @@ impl<R, O, M> CompositeContext<R, O, M>
     pub fn new(resource_loader: R, offloader: O, message_id_gen: M) -> Self {
         CompositeContext {
             inner: Arc::new((resource_loader, offloader, message_id_gen)),
+            limits: Default::default()
         }
     }
 
+    /// Sets the limits used when loading the resources of a mail.
+    ///
+    /// By default no limits are used.
+    pub fn with_loading_limits(mut self, limits: LoadingLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
     /// Returns a reference to the resource loader component.
     pub fn resource_loader(&self) -> &R {
         &self.inner.0
@@ -265,14 +382,30 @@ impl<R, O, M> Context for CompositeContext<R, O, M>
         self.resource_loader().transfer_encode_resource(data, self)
     }
 
+    fn use_preload(&self, preload: &IRI) {
+        self.resource_loader().use_preload(preload)
+    }
+
+    fn stop_using_preload(&self, preload: &IRI) {
+        self.resource_loader().stop_using_preload(preload)
+    }
+
     fn offload<F>(&self, fut: F) -> SendBoxFuture<F::Item, F::Error>
         where F: Future + Send + 'static,
               F::Item: Send+'static,
-              F::Error: Send+'static
+              F::Error: From<io::Error> + Send + 'static
     {
         self.offloader().offload(fut)
     }
 
+    fn offload_blocking_fn<FN, T, E>(&self, func: FN) -> SendBoxFuture<T, E>
+        where FN: FnOnce() -> Result<T, E> + Send + 'static,
+              T: Send + 'static,
+              E: From<io::Error> + Send + 'static
+    {
+        self.offloader().offload_blocking_fn(func)
+    }
+
     fn generate_content_id(&self) -> ContentId {
         self.id_gen().generate_content_id()
     }
@@ -281,6 +414,10 @@ impl<R, O, M> Context for CompositeContext<R, O, M>
         self.id_gen().generate_message_id()
     }
 
+    fn loading_limits(&self) -> LoadingLimits {
+        self.limits
+    }
+
 }
 
 /// Allows using a part of an context as an component.
@@ -303,10 +440,18 @@ impl<C> OffloaderComponent for C
     fn offload<F>(&self, fut: F) -> SendBoxFuture<F::Item, F::Error>
         where F: Future + Send + 'static,
               F::Item: Send+'static,
-              F::Error: Send+'static
+              F::Error: From<io::Error> + Send + 'static
     {
         <Self as Context>::offload(self, fut)
     }
+
+    fn offload_blocking_fn<FN, T, E>(&self, func: FN) -> SendBoxFuture<T, E>
+        where FN: FnOnce() -> Result<T, E> + Send + 'static,
+              T: Send + 'static,
+              E: From<io::Error> + Send + 'static
+    {
+        <Self as Context>::offload_blocking_fn(self, func)
+    }
 }
 
 /// Allows using a part of an context as an component.
@@ -325,4 +470,12 @@ impl<C> ResourceLoaderComponent for C
     {
         <Self as Context>::transfer_encode_resource(self, data)
     }
+
+    fn use_preload(&self, preload: &IRI) {
+        <Self as Context>::use_preload(self, preload)
+    }
+
+    fn stop_using_preload(&self, preload: &IRI) {
+        <Self as Context>::stop_using_preload(self, preload)
+    }
 }
\ No newline at end of file
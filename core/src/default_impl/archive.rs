@@ -0,0 +1,635 @@
+use std::{
+    cmp,
+    collections::HashMap,
+    fs::File,
+    io::{Read, Seek},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard}
+};
+
+use failure::Fail;
+use futures::{Future, IntoFuture};
+use flate2::read::GzDecoder;
+use zip::ZipArchive;
+
+use headers::header_components::FileMeta;
+
+use ::{
+    iri::IRI,
+    mime::sniff_media_type,
+    utils::SendBoxFuture,
+    error::{
+        ResourceLoadingError,
+        ResourceLoadingErrorKind
+    },
+    resource::{
+        Data,
+        EncData,
+        Metadata,
+        Source
+    },
+    context::{
+        Context,
+        ResourceLoaderComponent
+    }
+};
+use super::fs::{
+    SymlinkPolicy,
+    io_error_context,
+    is_confined_path,
+    open_confined
+};
+
+/// Separates the path of the archive from the path of the entry in an IRI.
+const ENTRY_SEPARATOR: &str = "!/";
+
+/// The maximal number of bytes pre-allocated for reading a zip entry.
+///
+/// The size stored in the archive can't be trusted, larger entries
+/// are still read but the buffer grows while reading.
+const MAX_PREALLOCATION: u64 = 1024 * 1024;
+
+/// The file entries of an archive by their (normalized) path.
+type ArchiveEntries = HashMap<String, Arc<[u8]>>;
+
+/// The lazily opened archive of a preload.
+type PreloadSlot = Arc<Mutex<Option<Arc<ArchiveEntries>>>>;
+
+#[derive(Debug)]
+struct Preload {
+    users: usize,
+    slot: PreloadSlot
+}
+
+/// A `ResourceLoaderComponent` loading resources from inside of zip and tar(.gz) archives.
+///
+/// Resources are addressed using jar-style IRIs, i.e. the path of the
+/// archive (relative to the loaders root) followed by `!/` and the path
+/// of the entry in the archive, e.g. `archive:templates.zip!/logo.png`.
+/// The archive format is determined by the file extension (`.zip`, `.tar`,
+/// `.tar.gz` or `.tgz`).
+///
+/// Without preloading the archive is opened for every resource loaded
+/// from it and only the requested entry is read (zip) or the archive is
+/// read up to the requested entry (tar). If `use_preload` is called with the IRI of an archive (e.g.
+/// `archive:templates.zip`, through `MailFutureBuilder::use_preloads`) the archive
+/// is opened once when the first resource is loaded from it, all entries
+/// are read and kept in memory until the matching `stop_using_preload` call.
+///
+/// Like with the `FsResourceLoader` the archive path is by default just
+/// joined with the root, use `confine_to_root` if the IRIs are (partially)
+/// user influenced. Media types are sniffed based on the content and the entry
+/// name, if not specified through the `Source`.
+///
+/// Loading fails with `ResourceLoadingErrorKind::UnknownScheme` if the IRI
+/// has a different scheme, with `MalformedIRI` if it doesn't point to an entry
+/// of an archive in a supported format and with `NotFound` if the archive or
+/// the entry doesn't exist. If the loader is confined to it's root loading
+/// archives outside of it fails with `Forbidden`.
+///
+/// Note that this loader is only available if the `default_impl_archive`
+/// feature is enabled.
+#[derive(Debug)]
+pub struct ArchiveResourceLoader {
+    root: PathBuf,
+    scheme: &'static str,
+    confinement: Option<SymlinkPolicy>,
+    preloads: Mutex<HashMap<PathBuf, Preload>>
+}
+
+impl ArchiveResourceLoader {
+
+    const DEFAULT_SCHEME: &'static str = "archive";
+
+    /// Creates a new loader for archives relative to given root using the `archive` scheme.
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self::new_with_scheme(root, Self::DEFAULT_SCHEME)
+    }
+
+    /// Creates a new loader for archives relative to given root using given scheme.
+    pub fn new_with_scheme<P: Into<PathBuf>>(root: P, scheme: &'static str) -> Self {
+        ArchiveResourceLoader {
+            root: root.into(),
+            scheme,
+            confinement: None,
+            preloads: Default::default()
+        }
+    }
+
+    /// Confines the loader to only load archives inside of it's root.
+    ///
+    /// This works the same way as `FsResourceLoader::confine_to_root`,
+    /// the path of the entry inside of the archive is not affected.
+    pub fn confine_to_root(mut self, policy: SymlinkPolicy) -> Self {
+        self.confinement = Some(policy);
+        self
+    }
+
+    /// Returns the symlink policy if the loader is confined to it's root.
+    pub fn confinement(&self) -> Option<SymlinkPolicy> {
+        self.confinement
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn scheme(&self) -> &'static str {
+        self.scheme
+    }
+
+    /// Returns the path of the archive a preload IRI refers to.
+    ///
+    /// The IRI can either point to the archive or an entry of it.
+    fn preload_path(&self, preload: &IRI) -> Option<PathBuf> {
+        if preload.scheme() != self.scheme {
+            return None;
+        }
+        let tail = preload.tail();
+        let archive = split_entry_tail(tail)
+            .map(|(archive, _)| archive)
+            .unwrap_or(tail);
+
+        Some(self.root.join(archive))
+    }
+}
+
+impl ResourceLoaderComponent for ArchiveResourceLoader {
+
+    fn load_resource(&self, source: &Source, ctx: &impl Context)
+        -> SendBoxFuture<EncData, ResourceLoadingError>
+    {
+        let split =
+            if source.iri.scheme() != self.scheme {
+                Err(ResourceLoadingErrorKind::UnknownScheme)
+            } else {
+                split_entry_tail(source.iri.tail())
+                    .ok_or(ResourceLoadingErrorKind::MalformedIRI)
+                    .and_then(|(archive, entry)| {
+                        if self.confinement.is_some() && !is_confined_path(Path::new(archive)) {
+                            Err(ResourceLoadingErrorKind::Forbidden)
+                        } else {
+                            Ok((archive, entry))
+                        }
+                    })
+            };
+
+        let (archive_path, entry) =
+            match split {
+                Ok((archive, entry)) => {
+                    (self.root.join(archive), normalize_entry_name(entry).to_owned())
+                },
+                Err(kind) => {
+                    let err = ResourceLoadingError::from((source.iri.clone(), kind));
+                    return Box::new(Err(err).into_future());
+                }
+            };
+
+        //UNWRAP_SAFE: we never panic while holding the lock
+        let slot = self.preloads.lock().unwrap()
+            .get(&archive_path)
+            .map(|preload| preload.slot.clone());
+
+        let confinement = self.confinement.map(|policy| (self.root.clone(), policy));
+        let source = source.clone();
+        let iri = source.iri.clone();
+        let content_id = ctx.generate_content_id();
+
+        let read_fut = ctx.offload_blocking_fn(move || -> Result<Data, ResourceLoadingError> {
+            let buffer =
+                match slot {
+                    Some(slot) => {
+                        let cached = lock_slot(&slot).clone();
+                        let entries =
+                            match cached {
+                                Some(entries) => entries,
+                                None => {
+                                    // read without holding the lock, so concurrent first
+                                    // loads might read the archive multiple times but
+                                    // the first stored entries are used by all of them
+                                    let (file, format) = open_archive(&archive_path, &confinement)?;
+                                    let entries = Arc::new(read_archive(file, format)?);
+                                    lock_slot(&slot).get_or_insert(entries).clone()
+                                }
+                            };
+
+                        entries.get(&entry)
+                            .cloned()
+                            .ok_or_else(|| ResourceLoadingError::from(ResourceLoadingErrorKind::NotFound))?
+                    },
+                    None => {
+                        let (file, format) = open_archive(&archive_path, &confinement)?;
+                        read_archive_entry(file, format, &entry)?
+                    }
+                };
+
+            let file_name = source.use_file_name.clone().or_else(|| {
+                Path::new(&entry).file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            });
+
+            let media_type = source.use_media_type.clone().resolve(None, || {
+                sniff_media_type(&buffer, file_name.as_ref().map(|name| &**name))
+            });
+
+            let file_meta = FileMeta {
+                file_name,
+                size: Some(buffer.len()),
+                ..Default::default()
+            };
+
+            Ok(Data::new(buffer, Metadata {
+                file_meta,
+                media_type,
+                content_id,
+                transfer_encoding_hint: source.use_transfer_encoding.unwrap_or_default(),
+                source: Some(source)
+            }))
+        });
+
+        let ctx = ctx.clone();
+        let fut = read_fut.and_then(move |data| {
+            ctx.offload_fn(move || Ok(data.transfer_encode(data.transfer_encoding_hint())))
+        });
+
+        Box::new(fut.map_err(move |err| err.with_source_iri_or_else(|| Some(iri))))
+    }
+
+    fn use_preload(&self, preload: &IRI) {
+        if let Some(path) = self.preload_path(preload) {
+            //UNWRAP_SAFE: we never panic while holding the lock
+            let mut preloads = self.preloads.lock().unwrap();
+            let preload = preloads.entry(path)
+                .or_insert_with(|| Preload { users: 0, slot: Default::default() });
+            preload.users += 1;
+        }
+    }
+
+    fn stop_using_preload(&self, preload: &IRI) {
+        if let Some(path) = self.preload_path(preload) {
+            //UNWRAP_SAFE: we never panic while holding the lock
+            let mut preloads = self.preloads.lock().unwrap();
+            let unused =
+                match preloads.get_mut(&path) {
+                    Some(preload) => {
+                        preload.users -= 1;
+                        preload.users == 0
+                    },
+                    // unpaired call, nothing to do
+                    None => false
+                };
+
+            if unused {
+                preloads.remove(&path);
+            }
+        }
+    }
+}
+
+fn lock_slot(slot: &PreloadSlot) -> MutexGuard<Option<Arc<ArchiveEntries>>> {
+    // the slot is always in a consistent state, so ignore poisoning
+    slot.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Splits the tail of an IRI into the archive path and the entry path.
+fn split_entry_tail(tail: &str) -> Option<(&str, &str)> {
+    let idx = tail.find(ENTRY_SEPARATOR)?;
+    let (archive, entry) = (&tail[..idx], &tail[idx + ENTRY_SEPARATOR.len()..]);
+    if archive.is_empty() || entry.is_empty() {
+        None
+    } else {
+        Some((archive, entry))
+    }
+}
+
+/// Removes leading `./` and `/` from the entry name.
+fn normalize_entry_name(mut name: &str) -> &str {
+    loop {
+        if name.starts_with("./") {
+            name = &name[2..];
+        } else if name.starts_with('/') {
+            name = &name[1..];
+        } else {
+            return name;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz
+}
+
+impl ArchiveFormat {
+    fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveFormat::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else {
+            None
+        }
+    }
+}
+
+/// Opens the archive at given path, confined to the root if `confinement` is set.
+fn open_archive(path: &Path, confinement: &Option<(PathBuf, SymlinkPolicy)>)
+    -> Result<(File, ArchiveFormat), ResourceLoadingError>
+{
+    let format = ArchiveFormat::from_path(path)
+        .ok_or_else(|| ResourceLoadingError::from(ResourceLoadingErrorKind::MalformedIRI))?;
+
+    let file =
+        match *confinement {
+            Some((ref root, policy)) => open_confined(path, root, policy)?,
+            None => File::open(path).map_err(io_error_context)?
+        };
+
+    Ok((file, format))
+}
+
+/// Reads all file entries of the archive, this is only used for preloads.
+fn read_archive(file: File, format: ArchiveFormat) -> Result<ArchiveEntries, ResourceLoadingError> {
+    match format {
+        ArchiveFormat::Zip => read_zip(file),
+        ArchiveFormat::Tar => read_tar(file),
+        ArchiveFormat::TarGz => read_tar(GzDecoder::new(file))
+    }
+}
+
+/// Reads only the file entry with given (normalized) name from the archive.
+fn read_archive_entry(file: File, format: ArchiveFormat, entry: &str)
+    -> Result<Arc<[u8]>, ResourceLoadingError>
+{
+    match format {
+        ArchiveFormat::Zip => read_zip_entry(file, entry),
+        ArchiveFormat::Tar => read_tar_entry(file, entry),
+        ArchiveFormat::TarGz => read_tar_entry(GzDecoder::new(file), entry)
+    }
+}
+
+fn read_zip<R: Read + Seek>(reader: R) -> Result<ArchiveEntries, ResourceLoadingError> {
+    let mut archive = ZipArchive::new(reader)
+        .map_err(|err| err.context(ResourceLoadingErrorKind::LoadingFailed))?;
+
+    let mut entries = HashMap::new();
+    for idx in 0..archive.len() {
+        let mut file = archive.by_index(idx)
+            .map_err(|err| err.context(ResourceLoadingErrorKind::LoadingFailed))?;
+
+        if file.name().ends_with('/') {
+            // directory entry
+            continue;
+        }
+
+        let name = normalize_entry_name(file.name()).to_owned();
+        let mut buffer = Vec::with_capacity(cmp::min(file.size(), MAX_PREALLOCATION) as usize);
+        file.read_to_end(&mut buffer)?;
+        entries.insert(name, buffer.into());
+    }
+    Ok(entries)
+}
+
+fn read_zip_entry<R: Read + Seek>(reader: R, entry: &str)
+    -> Result<Arc<[u8]>, ResourceLoadingError>
+{
+    let mut archive = ZipArchive::new(reader)
+        .map_err(|err| err.context(ResourceLoadingErrorKind::LoadingFailed))?;
+
+    // names in the archive might not be normalized, so we can't use `by_name` directly
+    let name = archive.file_names()
+        .find(|name| !name.ends_with('/') && normalize_entry_name(name) == entry)
+        .map(|name| name.to_owned())
+        .ok_or_else(|| ResourceLoadingError::from(ResourceLoadingErrorKind::NotFound))?;
+
+    let mut file = archive.by_name(&name)
+        .map_err(|err| err.context(ResourceLoadingErrorKind::LoadingFailed))?;
+
+    let mut buffer = Vec::with_capacity(cmp::min(file.size(), MAX_PREALLOCATION) as usize);
+    file.read_to_end(&mut buffer)?;
+    Ok(buffer.into())
+}
+
+fn read_tar<R: Read>(reader: R) -> Result<ArchiveEntries, ResourceLoadingError> {
+    let mut archive = ::tar::Archive::new(reader);
+
+    let mut entries = HashMap::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let name = {
+            let path = entry.path()?;
+            normalize_entry_name(&path.to_string_lossy()).to_owned()
+        };
+        let mut buffer = Vec::new();
+        entry.read_to_end(&mut buffer)?;
+        entries.insert(name, buffer.into());
+    }
+    Ok(entries)
+}
+
+/// Reads the archive until the entry with given name is found.
+///
+/// Tar archives have no index, so entries before it still need to be read
+/// (but are not kept in memory), later entries are not read at all.
+fn read_tar_entry<R: Read>(reader: R, name: &str) -> Result<Arc<[u8]>, ResourceLoadingError> {
+    let mut archive = ::tar::Archive::new(reader);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let matches = {
+            let path = entry.path()?;
+            normalize_entry_name(&path.to_string_lossy()) == name
+        };
+        if matches {
+            let mut buffer = Vec::new();
+            entry.read_to_end(&mut buffer)?;
+            return Ok(buffer.into());
+        }
+    }
+    Err(ResourceLoadingErrorKind::NotFound.into())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, io::Write};
+    use flate2::{Compression, write::GzEncoder};
+    use zip::{ZipWriter, write::FileOptions};
+    use default_impl::test_context;
+    use ::resource::UseMediaType;
+    use super::*;
+
+    const LOGO: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+    /// Creates `res.zip`, `res.tar` and `res.tar.gz` each containing
+    /// `logo.png` and `texts/hy.txt`.
+    fn setup(name: &str) -> PathBuf {
+        let dir = env::temp_dir()
+            .join(format!("mail_core_archive_test_{}_{}", name, ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut zip = ZipWriter::new(File::create(dir.join("res.zip")).unwrap());
+        zip.add_directory("texts/", FileOptions::default()).unwrap();
+        zip.start_file("logo.png", FileOptions::default()).unwrap();
+        zip.write_all(LOGO).unwrap();
+        zip.start_file("texts/hy.txt", FileOptions::default()).unwrap();
+        zip.write_all(b"hy there").unwrap();
+        zip.finish().unwrap();
+
+        fn append_entries<W: Write>(builder: &mut ::tar::Builder<W>) {
+            for &(name, content) in &[("./logo.png", LOGO), ("./texts/hy.txt", b"hy there" as &[u8])] {
+                let mut header = ::tar::Header::new_gnu();
+                header.set_size(content.len() as u64);
+                header.set_mode(0o644);
+                header.set_cksum();
+                builder.append_data(&mut header, name, content).unwrap();
+            }
+        }
+
+        let mut tar = ::tar::Builder::new(File::create(dir.join("res.tar")).unwrap());
+        append_entries(&mut tar);
+        tar.into_inner().unwrap();
+
+        let encoder = GzEncoder::new(File::create(dir.join("res.tar.gz")).unwrap(), Compression::default());
+        let mut tar_gz = ::tar::Builder::new(encoder);
+        append_entries(&mut tar_gz);
+        tar_gz.into_inner().unwrap().finish().unwrap();
+
+        dir
+    }
+
+    fn source(iri: &str) -> Source {
+        Source {
+            iri: IRI::new(iri).unwrap(),
+            use_media_type: UseMediaType::Auto,
+            use_file_name: None,
+            use_transfer_encoding: None
+        }
+    }
+
+    fn load(loader: &ArchiveResourceLoader, iri: &str) -> Result<Data, ResourceLoadingError> {
+        let enc_data = loader.load_resource(&source(iri), &test_context()).wait()?;
+        Ok(enc_data.transfer_decode().unwrap())
+    }
+
+    #[test]
+    fn loads_entries_from_all_formats() {
+        let dir = setup("formats");
+        let loader = ArchiveResourceLoader::new(&dir);
+
+        for archive in &["res.zip", "res.tar", "res.tar.gz"] {
+            let data = assert_ok!(load(&loader, &format!("archive:{}!/logo.png", archive)));
+            assert_eq!(&**data.buffer(), LOGO);
+            assert_eq!(data.media_type().as_str_repr(), "image/png");
+            assert_eq!(data.file_meta().file_name.as_ref().map(|s| &**s), Some("logo.png"));
+
+            let data = assert_ok!(load(&loader, &format!("archive:{}!/texts/hy.txt", archive)));
+            assert_eq!(&**data.buffer(), b"hy there");
+        }
+    }
+
+    #[test]
+    fn fails_for_missing_entries_and_archives() {
+        let dir = setup("missing");
+        let loader = ArchiveResourceLoader::new(&dir);
+
+        for iri in &["archive:res.zip!/nop.png", "archive:nop.zip!/logo.png"] {
+            let err = assert_err!(load(&loader, iri), iri);
+            assert_eq!(err.kind(), ResourceLoadingErrorKind::NotFound);
+            assert_eq!(err.source_iri().map(|iri| iri.as_str()), Some(*iri));
+        }
+    }
+
+    #[test]
+    fn fails_for_malformed_iris() {
+        let dir = setup("malformed");
+        let loader = ArchiveResourceLoader::new(&dir);
+
+        for iri in &["archive:res.zip", "archive:res.zip!/", "archive:res.rar!/logo.png"] {
+            let err = assert_err!(load(&loader, iri), iri);
+            assert_eq!(err.kind(), ResourceLoadingErrorKind::MalformedIRI);
+        }
+    }
+
+    #[test]
+    fn preloaded_archives_are_opened_once() {
+        let dir = setup("preload");
+        let loader = ArchiveResourceLoader::new(&dir);
+        let preload = IRI::new("archive:res.zip").unwrap();
+
+        loader.use_preload(&preload);
+        assert_ok!(load(&loader, "archive:res.zip!/logo.png"));
+
+        // the archive is still open so the entries can be loaded
+        fs::remove_file(dir.join("res.zip")).unwrap();
+        assert_ok!(load(&loader, "archive:res.zip!/texts/hy.txt"));
+
+        loader.stop_using_preload(&preload);
+        let err = assert_err!(load(&loader, "archive:res.zip!/logo.png"));
+        assert_eq!(err.kind(), ResourceLoadingErrorKind::NotFound);
+    }
+
+    #[test]
+    fn preloads_are_reference_counted() {
+        let dir = setup("preload_count");
+        let loader = ArchiveResourceLoader::new(&dir);
+        let preload = IRI::new("archive:res.tar").unwrap();
+
+        loader.use_preload(&preload);
+        loader.use_preload(&preload);
+        assert_ok!(load(&loader, "archive:res.tar!/logo.png"));
+        fs::remove_file(dir.join("res.tar")).unwrap();
+
+        loader.stop_using_preload(&preload);
+        assert_ok!(load(&loader, "archive:res.tar!/logo.png"));
+
+        loader.stop_using_preload(&preload);
+        assert_err!(load(&loader, "archive:res.tar!/logo.png"));
+    }
+
+    #[test]
+    fn confined_loader_rejects_archives_outside_of_root() {
+        let dir = setup("confined");
+        let loader = ArchiveResourceLoader::new(dir.join("sub"))
+            .confine_to_root(SymlinkPolicy::Refuse);
+        fs::create_dir_all(loader.root()).unwrap();
+
+        let err = assert_err!(load(&loader, "archive:../res.zip!/logo.png"));
+        assert_eq!(err.kind(), ResourceLoadingErrorKind::Forbidden);
+        assert_eq!(err.source_iri().map(|iri| iri.as_str()), Some("archive:../res.zip!/logo.png"));
+
+        fs::rename(dir.join("res.zip"), dir.join("sub/res.zip")).unwrap();
+        let data = assert_ok!(load(&loader, "archive:res.zip!/logo.png"));
+        assert_eq!(&**data.buffer(), LOGO);
+    }
+
+    #[test]
+    fn reads_single_entries_without_preload() {
+        let dir = setup("single_entry");
+        for archive in &["res.zip", "res.tar", "res.tar.gz"] {
+            let path = dir.join(archive);
+            let format = ArchiveFormat::from_path(&path).unwrap();
+
+            let file = File::open(&path).unwrap();
+            let buffer = assert_ok!(read_archive_entry(file, format, "texts/hy.txt"));
+            assert_eq!(&*buffer, b"hy there");
+
+            let file = File::open(&path).unwrap();
+            let err = assert_err!(read_archive_entry(file, format, "texts"));
+            assert_eq!(err.kind(), ResourceLoadingErrorKind::NotFound);
+        }
+    }
+}
@@ -0,0 +1,510 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex}
+};
+
+use futures::{Future, IntoFuture};
+
+use headers::header_components::ContentId;
+
+use ::{
+    iri::IRI,
+    utils::SendBoxFuture,
+    error::ResourceLoadingError,
+    resource::{
+        Data,
+        EncData,
+        Metadata,
+        Source,
+        TransferEncodingHint,
+        UseMediaType
+    },
+    context::{
+        Context,
+        ResourceLoaderComponent
+    },
+    default_impl::MemResourceLoader
+};
+
+/// A `ResourceLoaderComponent` caching the transfer encoded resources of another loader.
+///
+/// - Results of `load_resource` are cached by the sources IRI (and the
+///   media type, file name and transfer encoding overrides of the source).
+///   As every load is expected to produce a new content id a cache hit
+///   returns the cached (shared) buffer with a newly generated content id.
+/// - Results of `transfer_encode_resource` are cached by the content id
+///   of the data (and the transfer encoding hint), i.e. it is assumed that
+///   `Data` instances with the same content id have the same content. This
+///   is the case for `Data` instances returned by the default loaders.
+///
+/// The cache is bounded by a memory budget (the sum of the transfer encoded
+/// buffer sizes), if it is exceeded the least recently used entries are
+/// evicted. Resources larger than the budget are not cached at all.
+///
+/// Cached resources are never invalidated automatically, i.e. if the
+/// resource behind an IRI changes the old version is used until it's
+/// evicted. Use `invalidate` (or `clear`) after changing a resource. If
+/// a `MemResourceLoader` is wrapped use the `register`/`unregister` methods
+/// of the caching loader, which do so automatically.
+///
+/// Concurrently loading the same (not yet cached) resource will load it
+/// multiple times.
+///
+/// # Example
+///
+/// ```
+/// # extern crate mail_core;
+/// use mail_core::default_impl::{CachingResourceLoader, FsResourceLoader};
+///
+/// # fn main() {
+/// // cache up to 64MiB of transfer encoded resources
+/// let loader = CachingResourceLoader::new(
+///     FsResourceLoader::with_cwd_root().unwrap(),
+///     64 * 1024 * 1024
+/// );
+/// # let _ = loader;
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct CachingResourceLoader<R>
+    where R: ResourceLoaderComponent
+{
+    inner: R,
+    cache: Arc<Mutex<LruCache>>
+}
+
+impl<R> CachingResourceLoader<R>
+    where R: ResourceLoaderComponent
+{
+    /// Creates a new caching loader with given memory budget in bytes.
+    pub fn new(inner: R, memory_budget: usize) -> Self {
+        CachingResourceLoader {
+            inner,
+            cache: Arc::new(Mutex::new(LruCache::new(memory_budget)))
+        }
+    }
+
+    /// Returns a reference to the wrapped loader.
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns the memory budget in bytes.
+    pub fn memory_budget(&self) -> usize {
+        self.lock_cache().budget
+    }
+
+    /// Returns the number of bytes currently used by cached resources.
+    pub fn cached_bytes(&self) -> usize {
+        self.lock_cache().used
+    }
+
+    /// Removes all cached resources.
+    pub fn clear(&self) {
+        self.lock_cache().clear()
+    }
+
+    /// Removes all cached resources loaded from given IRI.
+    ///
+    /// Cached results of `transfer_encode_resource` are not affected as
+    /// they are keyed by the content id of the data.
+    pub fn invalidate(&self, iri: &IRI) {
+        self.lock_cache().invalidate(iri)
+    }
+
+    fn lock_cache(&self) -> ::std::sync::MutexGuard<LruCache> {
+        //UNWRAP_SAFE: we never panic while holding the lock
+        self.cache.lock().unwrap()
+    }
+}
+
+impl CachingResourceLoader<MemResourceLoader> {
+
+    /// Registers `data` with the wrapped loader and invalidates the cache for given IRI.
+    ///
+    /// See `MemResourceLoader::register`.
+    pub fn register(&self, iri: IRI, data: Data) -> Option<Data> {
+        let old = self.inner.register(iri.clone(), data);
+        self.invalidate(&iri);
+        old
+    }
+
+    /// Unregisters the data from the wrapped loader and invalidates the cache for given IRI.
+    ///
+    /// See `MemResourceLoader::unregister`.
+    pub fn unregister(&self, iri: &IRI) -> Option<Data> {
+        let old = self.inner.unregister(iri);
+        self.invalidate(iri);
+        old
+    }
+}
+
+impl<R> ResourceLoaderComponent for CachingResourceLoader<R>
+    where R: ResourceLoaderComponent
+{
+    fn load_resource(&self, source: &Source, ctx: &impl Context)
+        -> SendBoxFuture<EncData, ResourceLoadingError>
+    {
+        let key = CacheKey::Source(SourceKey::new(source));
+        let cached = self.lock_cache().get(&key);
+        if let Some(enc_data) = cached {
+            let enc_data = with_content_id(&enc_data, ctx.generate_content_id());
+            return Box::new(Ok(enc_data).into_future());
+        }
+
+        let cache = self.cache.clone();
+        Box::new(self.inner.load_resource(source, ctx).map(move |enc_data| {
+            //UNWRAP_SAFE: we never panic while holding the lock
+            cache.lock().unwrap().insert(key, enc_data.clone());
+            enc_data
+        }))
+    }
+
+    fn transfer_encode_resource(&self, data: &Data, ctx: &impl Context)
+        -> SendBoxFuture<EncData, ResourceLoadingError>
+    {
+        let key = CacheKey::Encoded(data.content_id().clone(), data.transfer_encoding_hint());
+        let cached = self.lock_cache().get(&key);
+        if let Some(enc_data) = cached {
+            return Box::new(Ok(enc_data).into_future());
+        }
+
+        let cache = self.cache.clone();
+        Box::new(self.inner.transfer_encode_resource(data, ctx).map(move |enc_data| {
+            //UNWRAP_SAFE: we never panic while holding the lock
+            cache.lock().unwrap().insert(key, enc_data.clone());
+            enc_data
+        }))
+    }
+
+    fn use_preload(&self, preload: &IRI) {
+        self.inner.use_preload(preload)
+    }
+
+    fn stop_using_preload(&self, preload: &IRI) {
+        self.inner.stop_using_preload(preload)
+    }
+}
+
+/// Creates a new `EncData` sharing the buffer but using a different content id.
+fn with_content_id(enc_data: &EncData, content_id: ContentId) -> EncData {
+    let meta = Metadata {
+        content_id,
+        ..(**enc_data.metadata()).clone()
+    };
+    EncData::new(enc_data.transfer_encoded_buffer().clone(), meta, enc_data.encoding())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum CacheKey {
+    Source(SourceKey),
+    Encoded(ContentId, TransferEncodingHint)
+}
+
+/// The parts of a `Source` which affect the loaded resource.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct SourceKey {
+    iri: IRI,
+    use_media_type: UseMediaType,
+    use_file_name: Option<String>,
+    use_transfer_encoding: Option<TransferEncodingHint>
+}
+
+impl SourceKey {
+    fn new(source: &Source) -> Self {
+        SourceKey {
+            iri: source.iri.clone(),
+            use_media_type: source.use_media_type.clone(),
+            use_file_name: source.use_file_name.clone(),
+            use_transfer_encoding: source.use_transfer_encoding
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    enc_data: EncData,
+    last_used: u64
+}
+
+/// A LRU cache bounded by the size of the cached buffers.
+#[derive(Debug)]
+struct LruCache {
+    budget: usize,
+    used: usize,
+    tick: u64,
+    entries: HashMap<CacheKey, CacheEntry>
+}
+
+impl LruCache {
+
+    fn new(budget: usize) -> Self {
+        LruCache {
+            budget,
+            used: 0,
+            tick: 0,
+            entries: HashMap::new()
+        }
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn get(&mut self, key: &CacheKey) -> Option<EncData> {
+        let tick = self.next_tick();
+        self.entries.get_mut(key).map(|entry| {
+            entry.last_used = tick;
+            entry.enc_data.clone()
+        })
+    }
+
+    fn insert(&mut self, key: CacheKey, enc_data: EncData) {
+        let size = enc_data.transfer_encoded_buffer().len();
+        if size > self.budget {
+            return;
+        }
+
+        self.remove(&key);
+        while self.used + size > self.budget {
+            self.evict_least_recently_used();
+        }
+
+        let last_used = self.next_tick();
+        self.used += size;
+        self.entries.insert(key, CacheEntry { enc_data, last_used });
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.used -= entry.enc_data.transfer_encoded_buffer().len();
+        }
+    }
+
+    fn evict_least_recently_used(&mut self) {
+        let lru_key = self.entries.iter()
+            .min_by_key(|&(_, entry)| entry.last_used)
+            .map(|(key, _)| key.clone());
+
+        if let Some(key) = lru_key {
+            self.remove(&key);
+        }
+    }
+
+    fn invalidate(&mut self, iri: &IRI) {
+        let keys = self.entries.keys()
+            .filter(|key| match **key {
+                CacheKey::Source(ref source_key) => source_key.iri == *iri,
+                CacheKey::Encoded(..) => false
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for key in keys {
+            self.remove(&key);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.used = 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use futures::Future;
+    use headers::header_components::{MediaType, TransferEncoding};
+    use default_impl::test_context;
+    use super::*;
+
+    /// Wraps a `MemResourceLoader` counting the calls to it.
+    #[derive(Debug, Default)]
+    struct CountingLoader {
+        inner: MemResourceLoader,
+        loads: AtomicUsize,
+        encodes: AtomicUsize
+    }
+
+    impl ResourceLoaderComponent for CountingLoader {
+        fn load_resource(&self, source: &Source, ctx: &impl Context)
+            -> SendBoxFuture<EncData, ResourceLoadingError>
+        {
+            self.loads.fetch_add(1, Ordering::SeqCst);
+            self.inner.load_resource(source, ctx)
+        }
+
+        fn transfer_encode_resource(&self, data: &Data, ctx: &impl Context)
+            -> SendBoxFuture<EncData, ResourceLoadingError>
+        {
+            self.encodes.fetch_add(1, Ordering::SeqCst);
+            self.inner.transfer_encode_resource(data, ctx)
+        }
+    }
+
+    /// Creates a caching loader with resources `mem:a`, `mem:b` and `mem:c`,
+    /// all of which have a transfer encoded size of 10 bytes.
+    fn caching_loader(memory_budget: usize) -> CachingResourceLoader<CountingLoader> {
+        let ctx = test_context();
+        let loader = CountingLoader::default();
+        for name in &["a", "b", "c"] {
+            let data = Data::plain_text(name.repeat(10), ctx.generate_content_id());
+            loader.inner.register(IRI::new(format!("mem:{}", name)).unwrap(), data);
+        }
+        CachingResourceLoader::new(loader, memory_budget)
+    }
+
+    fn source(iri: &str) -> Source {
+        Source {
+            iri: IRI::new(iri).unwrap(),
+            use_media_type: Default::default(),
+            use_file_name: None,
+            use_transfer_encoding: None
+        }
+    }
+
+    fn load(loader: &CachingResourceLoader<CountingLoader>, source: &Source) -> EncData {
+        loader.load_resource(source, &test_context()).wait().unwrap()
+    }
+
+    fn loads(loader: &CachingResourceLoader<CountingLoader>) -> usize {
+        loader.inner().loads.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn caches_loaded_resources() {
+        let loader = caching_loader(1024);
+
+        let first = load(&loader, &source("mem:a"));
+        let second = load(&loader, &source("mem:a"));
+
+        assert_eq!(loads(&loader), 1);
+        assert!(Arc::ptr_eq(first.transfer_encoded_buffer(), second.transfer_encoded_buffer()));
+        assert_ne!(first.content_id(), second.content_id());
+        assert_eq!(loader.cached_bytes(), 10);
+    }
+
+    #[test]
+    fn source_overrides_are_part_of_the_key() {
+        let loader = caching_loader(1024);
+
+        let mut html_source = source("mem:a");
+        html_source.use_media_type = UseMediaType::Override(MediaType::parse("text/html").unwrap());
+
+        load(&loader, &source("mem:a"));
+        let html = load(&loader, &html_source);
+
+        assert_eq!(loads(&loader), 2);
+        assert_eq!(html.media_type().as_str_repr(), "text/html");
+
+        let mut base64_source = source("mem:a");
+        base64_source.use_transfer_encoding = Some(TransferEncodingHint::UseBase64);
+        let base64 = load(&loader, &base64_source);
+
+        assert_eq!(loads(&loader), 3);
+        assert_eq!(base64.encoding(), TransferEncoding::Base64);
+    }
+
+    #[test]
+    fn caches_transfer_encoded_data_by_content_id() {
+        let loader = caching_loader(1024);
+        let ctx = test_context();
+        let data = Data::plain_text("hy there", ctx.generate_content_id());
+
+        let first = loader.transfer_encode_resource(&data, &ctx).wait().unwrap();
+        let second = loader.transfer_encode_resource(&data, &ctx).wait().unwrap();
+
+        assert_eq!(loader.inner().encodes.load(Ordering::SeqCst), 1);
+        assert!(Arc::ptr_eq(first.transfer_encoded_buffer(), second.transfer_encoded_buffer()));
+        assert_eq!(first.content_id(), second.content_id());
+    }
+
+    #[test]
+    fn evicts_least_recently_used_resources() {
+        let loader = caching_loader(20);
+
+        load(&loader, &source("mem:a"));
+        load(&loader, &source("mem:b"));
+        // makes `mem:b` the least recently used resource
+        load(&loader, &source("mem:a"));
+        load(&loader, &source("mem:c"));
+        assert_eq!(loads(&loader), 3);
+        assert_eq!(loader.cached_bytes(), 20);
+
+        load(&loader, &source("mem:a"));
+        load(&loader, &source("mem:c"));
+        assert_eq!(loads(&loader), 3);
+
+        load(&loader, &source("mem:b"));
+        assert_eq!(loads(&loader), 4);
+    }
+
+    #[test]
+    fn unloaded_resources_are_reloaded_from_the_cache() {
+        use ::resource::Resource;
+
+        let loader = caching_loader(1024);
+        let mut resource = Resource::EncData(load(&loader, &source("mem:a")));
+
+        assert_ok!(resource.unload());
+        let source = match resource {
+            Resource::Source(ref source) => source.clone(),
+            other => panic!("unexpected resource: {:?}", other)
+        };
+
+        load(&loader, &source);
+        assert_eq!(loads(&loader), 1);
+    }
+
+    #[test]
+    fn invalidate_removes_all_entries_of_the_iri() {
+        let loader = caching_loader(1024);
+
+        let mut html_source = source("mem:a");
+        html_source.use_media_type = UseMediaType::Override(MediaType::parse("text/html").unwrap());
+
+        load(&loader, &source("mem:a"));
+        load(&loader, &html_source);
+        load(&loader, &source("mem:b"));
+        assert_eq!(loader.cached_bytes(), 30);
+
+        loader.invalidate(&IRI::new("mem:a").unwrap());
+        assert_eq!(loader.cached_bytes(), 10);
+
+        load(&loader, &source("mem:a"));
+        load(&loader, &source("mem:b"));
+        assert_eq!(loads(&loader), 4);
+    }
+
+    #[test]
+    fn registering_through_the_cache_invalidates_it() {
+        let ctx = test_context();
+        let loader = CachingResourceLoader::new(MemResourceLoader::new(), 1024);
+        let iri = IRI::new("mem:a").unwrap();
+        loader.register(iri.clone(), Data::plain_text("old", ctx.generate_content_id()));
+
+        let old = loader.load_resource(&source("mem:a"), &ctx).wait().unwrap();
+        let cached = loader.load_resource(&source("mem:a"), &ctx).wait().unwrap();
+        assert!(Arc::ptr_eq(old.transfer_encoded_buffer(), cached.transfer_encoded_buffer()));
+
+        loader.register(iri.clone(), Data::plain_text("new", ctx.generate_content_id()));
+        let new = loader.load_resource(&source("mem:a"), &ctx).wait().unwrap();
+        assert_not!(Arc::ptr_eq(old.transfer_encoded_buffer(), new.transfer_encoded_buffer()));
+
+        loader.unregister(&iri);
+        assert_err!(loader.load_resource(&source("mem:a"), &ctx).wait());
+    }
+
+    #[test]
+    fn does_not_cache_resources_exceeding_the_budget() {
+        let loader = caching_loader(5);
+
+        load(&loader, &source("mem:a"));
+        load(&loader, &source("mem:a"));
+
+        assert_eq!(loads(&loader), 2);
+        assert_eq!(loader.cached_bytes(), 0);
+    }
+}
@@ -1,4 +1,6 @@
 
+use std::io;
+
 use futures::Future;
 use utils::SendBoxFuture;
 
@@ -20,7 +22,7 @@ impl OffloaderComponent for CpuPool {
     fn offload<F>(&self, fut: F) -> SendBoxFuture<F::Item, F::Error>
         where F: Future + Send + 'static,
               F::Item: Send+'static,
-              F::Error: Send+'static
+              F::Error: From<io::Error> + Send + 'static
     {
         Box::new( self.spawn( fut ) )
     }
@@ -40,7 +42,7 @@ mod test {
     }
 
     fn _check_if_it_works<R: OffloaderComponent>(r: R) {
-        let res = r.offload(future::lazy(||-> Result<u32,  ()> { Ok(33u32) } )).wait();
+        let res = r.offload(future::lazy(||-> Result<u32, io::Error> { Ok(33u32) } )).wait();
         let val = assert_ok!( res );
         assert_eq!( 33u32, val );
     }
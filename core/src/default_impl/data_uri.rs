@@ -0,0 +1,268 @@
+use failure::Fail;
+use futures::IntoFuture;
+
+use internals::bind::base64;
+use headers::header_components::{FileMeta, MediaType};
+
+use ::{
+    utils::SendBoxFuture,
+    error::{
+        ResourceLoadingError,
+        ResourceLoadingErrorKind
+    },
+    resource::{
+        Data,
+        EncData,
+        Metadata,
+        Source
+    },
+    context::{
+        Context,
+        ResourceLoaderComponent
+    }
+};
+
+/// The media type used by `data:` IRIs which do not specify one (RFC 2397).
+const DEFAULT_MEDIA_TYPE: &str = "text/plain;charset=US-ASCII";
+
+/// A `ResourceLoaderComponent` for `data:` IRIs as specified by RFC 2397.
+///
+/// Both percent-encoded and base64 encoded (`;base64`) payloads are
+/// supported. The media type (including parameters like `charset`)
+/// is taken from the IRI, if it doesn't contain one `text/plain;charset=US-ASCII`
+/// is used. `Source.use_media_type`, `Source.use_file_name` and
+/// `Source.use_transfer_encoding` are respected, with the media type in
+/// the IRI taking the place of a media type known by the loader (see
+/// `UseMediaType`).
+///
+/// The payload is decoded (and transfer encoded) using `Context::offload_fn`
+/// as it can be quite large. The loaded data has no `Metadata::source`, as
+/// it would contain the whole payload again. This also means resources
+/// loaded from `data:` IRIs can not be unloaded.
+///
+/// Loading a IRI with a scheme other than `data` fails with
+/// `ResourceLoadingErrorKind::UnknownScheme`, a malformed IRI fails
+/// with `ResourceLoadingErrorKind::MalformedIRI`.
+///
+/// # Example
+///
+/// ```
+/// # extern crate mail_core;
+/// use mail_core::default_impl::{DataUriLoader, FsResourceLoader, Mux};
+///
+/// # fn main() {
+/// let loader = Mux::new("path", FsResourceLoader::with_cwd_root().unwrap())
+///     .with_handler("data", DataUriLoader);
+/// # let _ = loader;
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DataUriLoader;
+
+impl DataUriLoader {
+    const SCHEME: &'static str = "data";
+}
+
+impl ResourceLoaderComponent for DataUriLoader {
+
+    fn load_resource(&self, source: &Source, ctx: &impl Context)
+        -> SendBoxFuture<EncData, ResourceLoadingError>
+    {
+        if source.iri.scheme() != Self::SCHEME {
+            let err = ResourceLoadingError
+                ::from(ResourceLoadingErrorKind::UnknownScheme)
+                .with_source_iri_or_else(|| Some(source.iri.clone()));
+            return Box::new(Err(err).into_future());
+        }
+
+        let iri = source.iri.clone();
+        let use_media_type = source.use_media_type.clone();
+        let use_file_name = source.use_file_name.clone();
+        let transfer_encoding_hint = source.use_transfer_encoding.unwrap_or_default();
+        let content_id = ctx.generate_content_id();
+
+        ctx.offload_fn(move || -> Result<EncData, ResourceLoadingError> {
+            let (media_type, buffer) = parse_data_uri(iri.tail())
+                .map_err(|err| err.with_source_iri_or_else(|| Some(iri.clone())))?;
+
+            let media_type = use_media_type.resolve(media_type, || {
+                //UNWRAP_SAFE: it's a constant valid media type
+                MediaType::parse(DEFAULT_MEDIA_TYPE).unwrap()
+            });
+
+            let file_meta = FileMeta {
+                file_name: use_file_name,
+                size: Some(buffer.len()),
+                ..Default::default()
+            };
+
+            let data = Data::new(buffer, Metadata {
+                file_meta,
+                media_type,
+                content_id,
+                // the IRI contains the whole payload, so don't keep it around
+                source: None,
+                transfer_encoding_hint
+            });
+
+            Ok(data.transfer_encode(transfer_encoding_hint))
+        })
+    }
+}
+
+/// Parses the tail of a `data:` IRI into it's media type and decoded payload.
+///
+/// The media type is `None` if the IRI doesn't specify it (neither
+/// through a mime type nor through parameters).
+fn parse_data_uri(tail: &str)
+    -> Result<(Option<MediaType>, Vec<u8>), ResourceLoadingError>
+{
+    let comma_idx = tail.find(',')
+        .ok_or_else(|| ResourceLoadingError::from(ResourceLoadingErrorKind::MalformedIRI))?;
+
+    let (mut meta, payload) = (&tail[..comma_idx], &tail[comma_idx+1..]);
+
+    const BASE64_SUFFIX: &str = ";base64";
+    let meta_len = meta.len();
+    let is_base64 = meta_len >= BASE64_SUFFIX.len()
+        && meta.is_char_boundary(meta_len - BASE64_SUFFIX.len())
+        && meta[meta_len - BASE64_SUFFIX.len()..].eq_ignore_ascii_case(BASE64_SUFFIX);
+
+    if is_base64 {
+        meta = &meta[..meta_len - BASE64_SUFFIX.len()];
+    }
+
+    let media_type =
+        if meta.is_empty() {
+            None
+        } else {
+            let meta = String::from_utf8(percent_decode(meta)?)
+                .map_err(|err| err.context(ResourceLoadingErrorKind::MalformedIRI))?;
+
+            // `data:;charset=utf-8,...` is a `text/plain` with given charset
+            let meta =
+                if meta.starts_with(';') {
+                    format!("text/plain{}", meta)
+                } else {
+                    meta
+                };
+
+            let media_type = MediaType::parse(&*meta)
+                .map_err(|err| err.context(ResourceLoadingErrorKind::MalformedIRI))?;
+
+            Some(media_type)
+        };
+
+    let mut buffer = percent_decode(payload)?;
+    if is_base64 {
+        // editors tend to insert line breaks into long base64 payloads
+        buffer.retain(|bch| !bch.is_ascii_whitespace());
+        buffer = base64::normal_decode(&buffer)
+            .map_err(|err| err.context(ResourceLoadingErrorKind::MalformedIRI))?;
+    }
+
+    Ok((media_type, buffer))
+}
+
+/// Decodes `%XX` escapes, all other characters are kept as they are.
+fn percent_decode(input: &str) -> Result<Vec<u8>, ResourceLoadingError> {
+    let input = input.as_bytes();
+    let mut out = Vec::with_capacity(input.len());
+    let mut idx = 0;
+    while idx < input.len() {
+        let bch = input[idx];
+        if bch == b'%' {
+            let high = input.get(idx+1).and_then(|&bch| hex_value(bch));
+            let low = input.get(idx+2).and_then(|&bch| hex_value(bch));
+            match (high, low) {
+                (Some(high), Some(low)) => out.push(high << 4 | low),
+                _ => return Err(ResourceLoadingErrorKind::MalformedIRI.into())
+            }
+            idx += 3;
+        } else {
+            out.push(bch);
+            idx += 1;
+        }
+    }
+    Ok(out)
+}
+
+fn hex_value(bch: u8) -> Option<u8> {
+    (bch as char).to_digit(16).map(|value| value as u8)
+}
+
+#[cfg(test)]
+mod test {
+    use futures::Future;
+    use default_impl::test_context;
+    use ::{IRI, resource::UseMediaType};
+    use super::*;
+
+    fn source(iri: &str) -> Source {
+        Source {
+            iri: IRI::new(iri).unwrap(),
+            use_media_type: Default::default(),
+            use_file_name: None,
+            use_transfer_encoding: None
+        }
+    }
+
+    fn load(source: &Source) -> Result<Data, ResourceLoadingError> {
+        let enc_data = DataUriLoader.load_resource(source, &test_context()).wait()?;
+        Ok(enc_data.transfer_decode().unwrap())
+    }
+
+    #[test]
+    fn loads_base64_payloads() {
+        let data = assert_ok!(load(&source("data:image/png;base64,iVBORw0K\r\nGgo=")));
+        assert_eq!(&**data.buffer(), b"\x89PNG\r\n\x1a\n");
+        assert_eq!(data.media_type().as_str_repr(), "image/png");
+        assert!(data.metadata().source.is_none());
+    }
+
+    #[test]
+    fn loads_percent_encoded_payloads() {
+        let data = assert_ok!(load(&source("data:text/plain;charset=utf-8,hy%20th%C3%A4re")));
+        assert_eq!(&**data.buffer(), "hy thäre".as_bytes());
+        assert_eq!(data.media_type().as_str_repr(), "text/plain;charset=utf-8");
+    }
+
+    #[test]
+    fn uses_rfc_default_media_type() {
+        let data = assert_ok!(load(&source("data:,hy%20there")));
+        assert_eq!(&**data.buffer(), b"hy there");
+        assert_eq!(data.media_type().as_str_repr(), DEFAULT_MEDIA_TYPE);
+    }
+
+    #[test]
+    fn charset_only_implies_text_plain() {
+        let data = assert_ok!(load(&source("data:;charset=utf-8,hy")));
+        assert_eq!(data.media_type().as_str_repr(), "text/plain;charset=utf-8");
+    }
+
+    #[test]
+    fn respects_source_settings() {
+        let mut source = source("data:text/plain,hy");
+        source.use_media_type = UseMediaType::Override(MediaType::parse("text/html").unwrap());
+        source.use_file_name = Some("hy.html".to_owned());
+
+        let data = assert_ok!(load(&source));
+        assert_eq!(data.media_type().as_str_repr(), "text/html");
+        assert_eq!(data.file_meta().file_name.as_ref().map(|s| &**s), Some("hy.html"));
+    }
+
+    #[test]
+    fn malformed_iris_fail() {
+        for iri in &["data:text/plain", "data:,%zz", "data:;base64,a!b?"] {
+            let err = assert_err!(load(&source(iri)));
+            assert_eq!(err.kind(), ResourceLoadingErrorKind::MalformedIRI);
+            assert_eq!(err.source_iri().map(|iri| iri.as_str()), Some(*iri));
+        }
+    }
+
+    #[test]
+    fn fails_for_other_schemes() {
+        let err = assert_err!(load(&source("path:,hy")));
+        assert_eq!(err.kind(), ResourceLoadingErrorKind::UnknownScheme);
+    }
+}
@@ -1,22 +1,19 @@
 use std::{
-    path::{Path, PathBuf},
-    fs::{self, File},
+    path::{Path, PathBuf, Component},
+    fs::{self, File, OpenOptions},
     io::{self, Read},
     env,
     marker::PhantomData,
 };
 
-use checked_command::CheckedCommand;
 use failure::Fail;
-use futures::IntoFuture;
+use futures::{Future, IntoFuture};
 
-use headers::header_components::{
-    MediaType,
-    FileMeta
-};
+use headers::header_components::FileMeta;
 
 use ::{
     iri::IRI,
+    mime::sniff_media_type,
     utils::{
         SendBoxFuture,
         ConstSwitch, Enabled
@@ -40,22 +37,37 @@ use ::{
 
 // have a scheme ignoring variant for Mux as the scheme is preset
 // allow a setup with different scheme path/file etc. the behavior stays the same!
-// do not handle sandboxing/security as such do not handle "file" only "path" ~use open_at if available?~
+// sandboxing is opt-in (see `confine_to_root`), as such do not handle "file" only "path" ~use open_at if available?~
 
 //TODO more doc
 /// By setting SchemeValidation to Disabled the FsResourceLoader can be used to simple
 /// load a resource from a file based on a scheme tail as path independent of the rest,
 /// so e.g. it it is used in a `Mux` which selects a `ResourceLoader` impl based on a scheme
 /// the scheme would not be double validated.
+///
+/// By default the IRI tail is just joined with the root, which means e.g.
+/// `path:../../etc/passwd` can escape the root. Use `confine_to_root` if
+/// the IRIs are (partially) user influenced.
 #[derive( Debug, Clone, PartialEq, Default )]
 pub struct FsResourceLoader<
     SchemeValidation: ConstSwitch = Enabled,
 > {
     root: PathBuf,
     scheme: &'static str,
+    confinement: Option<SymlinkPolicy>,
     _marker: PhantomData<SchemeValidation>
 }
 
+/// Specifies how symlinks are handled by a `FsResourceLoader` confined to it's root.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SymlinkPolicy {
+    /// Follow symlinks as long as the resolved path is still inside of the root.
+    FollowInsideRoot,
+
+    /// Refuse to load any path which goes through a symlink (inside of the root).
+    Refuse
+}
+
 impl<SVSw> FsResourceLoader<SVSw>
     where SVSw: ConstSwitch
 {
@@ -69,7 +81,26 @@ impl<SVSw> FsResourceLoader<SVSw>
     }
 
     pub fn new_with_scheme<P: Into<PathBuf>>( root: P, scheme: &'static str ) -> Self {
-        FsResourceLoader { root: root.into(), scheme, _marker: PhantomData}
+        FsResourceLoader { root: root.into(), scheme, confinement: None, _marker: PhantomData}
+    }
+
+    /// Confines the loader to only load files inside of it's root.
+    ///
+    /// Paths with `..`, absolute paths and (on windows) paths with
+    /// a prefix are rejected. Additionally the path is canonicalized
+    /// before loading and symlinks are either followed as long as they
+    /// do not escape the root or refused depending on the `policy`.
+    ///
+    /// If loading a resource is rejected it fails with
+    /// `ResourceLoadingErrorKind::Forbidden`.
+    pub fn confine_to_root(mut self, policy: SymlinkPolicy) -> Self {
+        self.confinement = Some(policy);
+        self
+    }
+
+    /// Returns the symlink policy if the loader is confined to it's root.
+    pub fn confinement(&self) -> Option<SymlinkPolicy> {
+        self.confinement
     }
 
     pub fn with_cwd_root() -> Result<Self, io::Error> {
@@ -110,17 +141,153 @@ impl<ValidateScheme> ResourceLoaderComponent for FsResourceLoader<ValidateScheme
             return Box::new(Err(err).into_future());
         }
 
-        let path = self.root().join(path_from_tail(&source.iri));
+        let tail_path = path_from_tail(&source.iri);
+        let confinement =
+            match self.confinement {
+                None => None,
+                Some(policy) => {
+                    if !is_confined_path(tail_path) {
+                        let err = ResourceLoadingError
+                            ::from(ResourceLoadingErrorKind::Forbidden)
+                            .with_source_iri_or_else(|| Some(source.iri.clone()));
+
+                        return Box::new(Err(err).into_future());
+                    }
+                    Some((self.root().to_owned(), policy))
+                }
+            };
+
+        let path = self.root().join(tail_path);
         let use_media_type = source.use_media_type.clone();
         let use_file_name = source.use_file_name.clone();
+        let iri = source.iri.clone();
 
-        load_data(
+        let fut = _load_data(
             path,
+            confinement,
             use_media_type,
             use_file_name,
+            Some(source.clone()),
             ctx,
-            |data| Ok(data.transfer_encode(Default::default()))
-        )
+            |data| Ok(data.transfer_encode(data.transfer_encoding_hint()))
+        );
+
+        Box::new(fut.map_err(move |err| err.with_source_iri_or_else(|| Some(iri))))
+    }
+}
+
+/// Checks that the path has no `..`, root or prefix components.
+pub(crate) fn is_confined_path(path: &Path) -> bool {
+    path.components().all(|component| match component {
+        Component::Normal(_) | Component::CurDir => true,
+        Component::ParentDir | Component::RootDir | Component::Prefix(_) => false
+    })
+}
+
+/// Resolves the path making sure it doesn't escape the root.
+///
+/// `path` is expected to be `root` joined with a path which passed
+/// `is_confined_path`.
+fn resolve_confined_path(path: &Path, root: &Path, policy: SymlinkPolicy)
+    -> Result<PathBuf, ResourceLoadingError>
+{
+    let canonical_root = root.canonicalize().map_err(io_error_context)?;
+
+    match policy {
+        SymlinkPolicy::FollowInsideRoot => {
+            let canonical_path = path.canonicalize().map_err(io_error_context)?;
+            if canonical_path.starts_with(&canonical_root) {
+                Ok(canonical_path)
+            } else {
+                Err(ResourceLoadingErrorKind::Forbidden.into())
+            }
+        },
+        SymlinkPolicy::Refuse => {
+            let relative = path.strip_prefix(root)
+                .map_err(|_| ResourceLoadingError::from(ResourceLoadingErrorKind::Forbidden))?;
+
+            let mut resolved = canonical_root;
+            for component in relative.components() {
+                if let Component::CurDir = component {
+                    continue;
+                }
+                resolved.push(component);
+                let meta = fs::symlink_metadata(&resolved).map_err(io_error_context)?;
+                if meta.file_type().is_symlink() {
+                    return Err(ResourceLoadingErrorKind::Forbidden.into());
+                }
+            }
+            Ok(resolved)
+        }
+    }
+}
+
+/// Opens the file at `path` making sure it doesn't escape the root.
+///
+/// `path` is expected to be `root` joined with a path which passed
+/// `is_confined_path`. After resolving the path (see `resolve_confined_path`)
+/// it is opened without following symlinks (on unix) and the opened file is
+/// checked to be the one which was resolved. This way a symlink swapped in
+/// between resolving and opening the path can't be used to escape the root.
+pub(crate) fn open_confined(path: &Path, root: &Path, policy: SymlinkPolicy)
+    -> Result<File, ResourceLoadingError>
+{
+    let resolved = resolve_confined_path(path, root, policy)?;
+    let checked = fs::symlink_metadata(&resolved).map_err(io_error_context)?;
+    if checked.file_type().is_symlink() {
+        return Err(ResourceLoadingErrorKind::Forbidden.into());
+    }
+
+    let file = open_no_follow(&resolved)?;
+    let opened = file.metadata().map_err(io_error_context)?;
+    if !is_same_file(&checked, &opened) {
+        return Err(ResourceLoadingErrorKind::Forbidden.into());
+    }
+    Ok(file)
+}
+
+#[cfg(unix)]
+fn open_no_follow(path: &Path) -> Result<File, ResourceLoadingError> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let file = OpenOptions::new()
+        .read(true)
+        .custom_flags(::libc::O_NOFOLLOW)
+        .open(path)
+        .map_err(|err| {
+            // opening a symlink with O_NOFOLLOW fails with ELOOP
+            if err.raw_os_error() == Some(::libc::ELOOP) {
+                err.context(ResourceLoadingErrorKind::Forbidden)
+            } else {
+                io_error_context(err)
+            }
+        })?;
+    Ok(file)
+}
+
+#[cfg(not(unix))]
+fn open_no_follow(path: &Path) -> Result<File, ResourceLoadingError> {
+    Ok(File::open(path).map_err(io_error_context)?)
+}
+
+#[cfg(unix)]
+fn is_same_file(checked: &fs::Metadata, opened: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    checked.dev() == opened.dev() && checked.ino() == opened.ino()
+}
+
+/// There is no portable way to compare the identity of files, so on
+/// non unix platforms only the checks done when resolving the path apply.
+#[cfg(not(unix))]
+fn is_same_file(_checked: &fs::Metadata, _opened: &fs::Metadata) -> bool {
+    true
+}
+
+pub(crate) fn io_error_context(err: io::Error) -> ::failure::Context<ResourceLoadingErrorKind> {
+    if err.kind() == io::ErrorKind::NotFound {
+        err.context(ResourceLoadingErrorKind::NotFound)
+    } else {
+        err.context(ResourceLoadingErrorKind::LoadingFailed)
     }
 }
 
@@ -139,17 +306,39 @@ pub fn load_data<R, F>(
 ) -> SendBoxFuture<R, ResourceLoadingError>
     where R: Send + 'static,
           F: FnOnce(Data) -> Result<R, ResourceLoadingError> + Send + 'static
+{
+    _load_data(path, None, use_media_type, use_file_name, None, ctx, post_process)
+}
+
+/// Like `load_data` but optionally confines the path to a root.
+///
+/// The file name is always taken from `path` even if it is a
+/// symlink which was followed. The `source` is stored in the
+/// metadata of the loaded data and it's `use_transfer_encoding`
+/// is used as the datas transfer encoding hint.
+fn _load_data<R, F>(
+    path: PathBuf,
+    confinement: Option<(PathBuf, SymlinkPolicy)>,
+    use_media_type: UseMediaType,
+    use_file_name: Option<String>,
+    source: Option<Source>,
+    ctx: &impl Context,
+    post_process: F,
+) -> SendBoxFuture<R, ResourceLoadingError>
+    where R: Send + 'static,
+          F: FnOnce(Data) -> Result<R, ResourceLoadingError> + Send + 'static
 {
     let content_id = ctx.generate_content_id();
-    ctx.offload_fn(move || {
-        let mut fd = File::open(&path)
-            .map_err(|err| {
-                if err.kind() == io::ErrorKind::NotFound {
-                    err.context(ResourceLoadingErrorKind::NotFound)
-                } else {
-                    err.context(ResourceLoadingErrorKind::LoadingFailed)
-                }
-            })?;
+    let transfer_encoding_hint = source.as_ref()
+        .and_then(|source| source.use_transfer_encoding)
+        .unwrap_or_default();
+
+    let read_fut = ctx.offload_blocking_fn(move || -> Result<Data, ResourceLoadingError> {
+        let mut fd =
+            match confinement {
+                Some((root, policy)) => open_confined(&path, &root, policy)?,
+                None => File::open(&path).map_err(io_error_context)?
+            };
 
         let mut file_meta = file_meta_from_metadata(fd.metadata()?);
 
@@ -163,45 +352,25 @@ pub fn load_data<R, F>(
         let mut buffer = Vec::new();
         fd.read_to_end(&mut buffer)?;
 
-        let media_type =
-            match use_media_type {
-                UseMediaType::Auto => {
-                    sniff_media_type(&path)?
-                },
-                UseMediaType::Default(media_type) => {
-                    media_type
-                }
-            };
+        // the file system doesn't provide any media type information
+        let media_type = use_media_type.resolve(None, || {
+            sniff_media_type(&buffer, file_meta.file_name.as_ref().map(|name| &**name))
+        });
 
         let data = Data::new(buffer, Metadata {
             file_meta,
             content_id,
             media_type,
+            source,
+            transfer_encoding_hint
         });
 
-        post_process(data)
-    })
+        Ok(data)
+    });
 
-}
-
-fn sniff_media_type(path: impl AsRef<Path>) -> Result<MediaType, ResourceLoadingError> {
-    //TODO replace current  impl with conservative sniffing
-    let output = CheckedCommand
-        ::new("file")
-        .args(&["--brief", "--mime"])
-        .arg(path.as_ref())
-        .output()
-        .map_err(|err| err.context(ResourceLoadingErrorKind::MediaTypeDetectionFailed))?;
-
-    let raw_media_type = String
-        ::from_utf8(output.stdout)
-        .map_err(|err| err.context(ResourceLoadingErrorKind::MediaTypeDetectionFailed))?;
-
-    let media_type = MediaType
-        ::parse(raw_media_type.trim())
-        .map_err(|err| err.context(ResourceLoadingErrorKind::MediaTypeDetectionFailed))?;
-
-    Ok(media_type)
+    // post processing (e.g. transfer encoding) is CPU work not blocking IO
+    let ctx = ctx.clone();
+    Box::new(read_fut.and_then(move |data| ctx.offload_fn(move || post_process(data))))
 }
 
 //TODO implement From<MetaDate> for FileMeta instead of this
@@ -247,16 +416,150 @@ mod tests {
 
 
     mod sniff_media_type {
+        use futures::Future;
+        use default_impl::test_context;
         use super::super::*;
 
         #[test]
         fn works_reasonable_for_cargo_files() {
-            let res = sniff_media_type("./Cargo.lock")
+            let loader = FsResourceLoader::new(env!("CARGO_MANIFEST_DIR"));
+            let source = Source {
+                iri: IRI::new("path:Cargo.toml").unwrap(),
+                use_media_type: UseMediaType::Auto,
+                use_file_name: None,
+                use_transfer_encoding: None
+            };
+            let data = loader.load_resource(&source, &test_context())
+                .wait()
                 .unwrap();
 
-            // it currently doesn't take advantage of file endings so
-            // all pure "text" will be text/plain
-            assert_eq!(res.as_str_repr(), "text/plain; charset=us-ascii");
+            // `.toml` is not a known extension so it's detected as plain text
+            assert_eq!(data.media_type().as_str_repr(), "text/plain; charset=us-ascii");
+        }
+    }
+
+    mod transfer_encoding {
+        use futures::Future;
+        use headers::header_components::TransferEncoding;
+        use default_impl::test_context;
+        use ::resource::TransferEncodingHint;
+        use super::super::*;
+
+        #[test]
+        fn respects_source_preference() {
+            let loader = FsResourceLoader::new(env!("CARGO_MANIFEST_DIR"));
+            let source = Source {
+                iri: IRI::new("path:Cargo.toml").unwrap(),
+                use_media_type: UseMediaType::Auto,
+                use_file_name: None,
+                use_transfer_encoding: Some(TransferEncodingHint::UseQuotedPrintable)
+            };
+            let data = loader.load_resource(&source, &test_context())
+                .wait()
+                .unwrap();
+
+            assert_eq!(data.encoding(), TransferEncoding::QuotedPrintable);
+            assert_eq!(data.metadata().transfer_encoding_hint, TransferEncodingHint::UseQuotedPrintable);
+        }
+    }
+
+    mod confine_to_root {
+        use std::env;
+        use futures::Future;
+        use headers::header_components::MediaType;
+        use default_impl::test_context;
+        use super::super::*;
+
+        /// Creates `<tmp>/<name>/root/file.txt` and `<tmp>/<name>/outside.txt`.
+        fn setup(name: &str) -> PathBuf {
+            let dir = env::temp_dir()
+                .join(format!("mail_core_fs_test_{}_{}", name, ::std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(dir.join("root")).unwrap();
+            fs::write(dir.join("root").join("file.txt"), b"inside").unwrap();
+            fs::write(dir.join("outside.txt"), b"outside").unwrap();
+            dir
+        }
+
+        fn load(loader: &FsResourceLoader, iri: &str)
+            -> Result<EncData, ResourceLoadingError>
+        {
+            let source = Source {
+                iri: IRI::new(iri).unwrap(),
+                use_media_type: UseMediaType::Default(MediaType::parse("text/plain").unwrap()),
+                use_file_name: None,
+                use_transfer_encoding: None
+            };
+            loader.load_resource(&source, &test_context()).wait()
+        }
+
+        #[test]
+        fn loads_files_inside_of_root() {
+            let dir = setup("inside");
+            let loader = FsResourceLoader::new(dir.join("root"))
+                .confine_to_root(SymlinkPolicy::Refuse);
+
+            let data = assert_ok!(load(&loader, "path:file.txt"));
+            assert_eq!(&**assert_ok!(data.transfer_decode()).buffer(), b"inside");
+        }
+
+        #[test]
+        fn rejects_parent_dir_and_absolute_paths() {
+            let dir = setup("traversal");
+            let loader = FsResourceLoader::new(dir.join("root"))
+                .confine_to_root(SymlinkPolicy::FollowInsideRoot);
+
+            for iri in &["path:../outside.txt", "path:/etc/passwd", "path:///etc/passwd"] {
+                let err = assert_err!(load(&loader, iri), iri);
+                assert_eq!(err.kind(), ResourceLoadingErrorKind::Forbidden);
+            }
+        }
+
+        #[test]
+        fn unconfined_loader_does_not_reject_parent_dir() {
+            let dir = setup("unconfined");
+            let loader = FsResourceLoader::new(dir.join("root"));
+
+            let data = assert_ok!(load(&loader, "path:../outside.txt"));
+            assert_eq!(&**assert_ok!(data.transfer_decode()).buffer(), b"outside");
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn symlinks_are_handled_based_on_policy() {
+            use std::os::unix::fs::symlink;
+
+            let dir = setup("symlinks");
+            let root = dir.join("root");
+            symlink(dir.join("outside.txt"), root.join("escape.txt")).unwrap();
+            symlink(root.join("file.txt"), root.join("link.txt")).unwrap();
+
+            let follow = FsResourceLoader::new(&root)
+                .confine_to_root(SymlinkPolicy::FollowInsideRoot);
+            let err = assert_err!(load(&follow, "path:escape.txt"));
+            assert_eq!(err.kind(), ResourceLoadingErrorKind::Forbidden);
+            let data = assert_ok!(load(&follow, "path:link.txt"));
+            assert_eq!(data.file_meta().file_name, Some("link.txt".to_owned()));
+
+            let refuse = FsResourceLoader::new(&root)
+                .confine_to_root(SymlinkPolicy::Refuse);
+            let err = assert_err!(load(&refuse, "path:link.txt"));
+            assert_eq!(err.kind(), ResourceLoadingErrorKind::Forbidden);
+            assert_eq!(err.source_iri().map(|iri| iri.as_str()), Some("path:link.txt"));
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn opening_does_not_follow_symlinks() {
+            use std::os::unix::fs::symlink;
+
+            let dir = setup("no_follow");
+            let root = dir.join("root");
+            symlink(dir.join("outside.txt"), root.join("escape.txt")).unwrap();
+
+            // e.g. a symlink swapped in after the path had been resolved
+            let err = assert_err!(open_no_follow(&root.join("escape.txt")));
+            assert_eq!(err.kind(), ResourceLoadingErrorKind::Forbidden);
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,72 @@
+use std::io;
+
+use futures::Future;
+
+use utils::SendBoxFuture;
+use context::OffloaderComponent;
+
+/// A `OffloaderComponent` which doesn't offload anything.
+///
+/// Offloaded futures and functions are run on the thread polling the
+/// returned future, i.e. no thread pool is needed. This is mainly meant
+/// for CLI tools and tests, e.g. in combination with
+/// `Mail::into_encodable_mail_blocking`.
+///
+/// Note that this means blocking IO (e.g. reading a file) and CPU heavy
+/// work (e.g. transfer encoding) is done inside of `poll`, so this should
+/// not be used with futures polled by an event loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct InlineOffloader;
+
+impl OffloaderComponent for InlineOffloader {
+
+    fn offload<F>(&self, fut: F) -> SendBoxFuture<F::Item, F::Error>
+        where F: Future + Send + 'static,
+              F::Item: Send+'static,
+              F::Error: From<io::Error> + Send + 'static
+    {
+        Box::new(fut)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+    use headers::{
+        headers::_From,
+        header_components::MediaType
+    };
+    use ::{
+        IRI, Mail, Resource,
+        context::Context,
+        resource::{Data, Source, UseMediaType},
+        default_impl::test_context_with_offloader
+    };
+    use super::*;
+
+    #[test]
+    fn runs_on_the_calling_thread() {
+        let current = thread::current().id();
+        let res = InlineOffloader.offload_blocking_fn(move || -> Result<_, io::Error> {
+            Ok(thread::current().id() == current)
+        }).wait();
+        assert!(assert_ok!(res));
+    }
+
+    test!(into_encodable_mail_blocking_works_without_thread_pool, {
+        let ctx = test_context_with_offloader(InlineOffloader);
+        let data = Data::plain_text("hy there", ctx.generate_content_id());
+        ctx.resource_loader().register(IRI::new("mem:greeting").unwrap(), data);
+
+        let source = Source {
+            iri: IRI::new("mem:greeting").unwrap(),
+            use_media_type: UseMediaType::Default(MediaType::parse("text/plain").unwrap()),
+            use_file_name: None,
+            use_transfer_encoding: None
+        };
+        let mut mail = Mail::new_singlepart_mail(Resource::Source(source));
+        mail.insert_headers(headers! { _From: ["random@this.is.no.mail"] }?);
+
+        mail.into_encodable_mail_blocking(ctx)?;
+    });
+}
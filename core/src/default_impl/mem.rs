@@ -0,0 +1,219 @@
+use std::{
+    collections::HashMap,
+    sync::RwLock
+};
+
+use futures::IntoFuture;
+
+use ::{
+    iri::IRI,
+    utils::SendBoxFuture,
+    error::{
+        ResourceLoadingError,
+        ResourceLoadingErrorKind
+    },
+    resource::{
+        Data,
+        EncData,
+        Metadata,
+        Source
+    },
+    context::{
+        Context,
+        ResourceLoaderComponent
+    }
+};
+
+/// A `ResourceLoaderComponent` loading `Data` registered under an IRI from memory.
+///
+/// This is useful for tests and for resources generated at startup
+/// (e.g. a rendered logo). The loader doesn't care about the IRI
+/// scheme, it just looks up the whole IRI, so it can be combined
+/// with other loaders using a `Mux` (e.g. with the `mem` scheme).
+///
+/// Like when loading data from a file every load gets a newly generated
+/// content id. The media type, file name and transfer encoding hint of
+/// the registered `Data` are used, except if overridden by the `Source`
+/// (see `UseMediaType`).
+///
+/// # Example
+///
+/// ```
+/// # extern crate mail_core;
+/// # extern crate mail_headers;
+/// # use mail_headers::header_components::Domain;
+/// use mail_core::{
+///     IRI, Data, Context,
+///     default_impl::{simple_context, MemResourceLoader, Mux}
+/// };
+///
+/// # fn main() {
+/// # let domain = Domain::from_unchecked("example.com".to_owned());
+/// # let ctx = simple_context::new(domain, "xm3r2u".parse().unwrap()).unwrap();
+/// let loader = MemResourceLoader::new();
+/// let data = Data::plain_text("hy there", ctx.generate_content_id());
+/// loader.register(IRI::new("mem:greeting").unwrap(), data);
+/// let loader = Mux::new("mem", loader);
+/// # let _ = loader;
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct MemResourceLoader {
+    resources: RwLock<HashMap<IRI, Data>>
+}
+
+impl MemResourceLoader {
+
+    /// Creates a new loader without any registered data.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Registers `data` under the given IRI.
+    ///
+    /// Returns the data previously registered under the same IRI, if any.
+    pub fn register(&self, iri: IRI, data: Data) -> Option<Data> {
+        //UNWRAP_SAFE: we never panic while holding the lock
+        self.resources.write().unwrap().insert(iri, data)
+    }
+
+    /// Removes the data registered under the given IRI and returns it.
+    pub fn unregister(&self, iri: &IRI) -> Option<Data> {
+        //UNWRAP_SAFE: we never panic while holding the lock
+        self.resources.write().unwrap().remove(iri)
+    }
+
+    /// Returns true if data is registered under given IRI.
+    pub fn contains(&self, iri: &IRI) -> bool {
+        //UNWRAP_SAFE: we never panic while holding the lock
+        self.resources.read().unwrap().contains_key(iri)
+    }
+}
+
+impl ResourceLoaderComponent for MemResourceLoader {
+
+    fn load_resource(&self, source: &Source, ctx: &impl Context)
+        -> SendBoxFuture<EncData, ResourceLoadingError>
+    {
+        //UNWRAP_SAFE: we never panic while holding the lock
+        let registered = self.resources.read().unwrap().get(&source.iri).cloned();
+        let data =
+            match registered {
+                Some(data) => data,
+                None => {
+                    let err = ResourceLoadingError
+                        ::from(ResourceLoadingErrorKind::NotFound)
+                        .with_source_iri_or_else(|| Some(source.iri.clone()));
+
+                    return Box::new(Err(err).into_future());
+                }
+            };
+
+        let mut file_meta = data.file_meta().clone();
+        if let Some(ref name) = source.use_file_name {
+            file_meta.file_name = Some(name.clone());
+        }
+
+        let media_type = source.use_media_type.clone()
+            .resolve(Some(data.media_type().clone()), || data.media_type().clone());
+
+        let data = Data::new(data.buffer().clone(), Metadata {
+            file_meta,
+            media_type,
+            content_id: ctx.generate_content_id(),
+            source: Some(source.clone()),
+            transfer_encoding_hint: source.use_transfer_encoding
+                .unwrap_or(data.transfer_encoding_hint())
+        });
+
+        self.transfer_encode_resource(&data, ctx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::Future;
+    use headers::header_components::MediaType;
+    use default_impl::test_context;
+    use ::resource::UseMediaType;
+    use super::*;
+
+    fn source(iri: &str, use_media_type: UseMediaType) -> Source {
+        Source {
+            iri: IRI::new(iri).unwrap(),
+            use_media_type,
+            use_file_name: None,
+            use_transfer_encoding: None
+        }
+    }
+
+    fn loader_with_text(iri: &str, text: &str) -> MemResourceLoader {
+        let loader = MemResourceLoader::new();
+        let data = Data::plain_text(text, test_context().generate_content_id());
+        assert!(loader.register(IRI::new(iri).unwrap(), data).is_none());
+        loader
+    }
+
+    #[test]
+    fn loads_registered_data() {
+        let loader = loader_with_text("mem:greeting", "hy there");
+        let source = source("mem:greeting", Default::default());
+
+        let enc_data = assert_ok!(loader.load_resource(&source, &test_context()).wait());
+        let data = assert_ok!(enc_data.transfer_decode());
+        assert_eq!(&**data.buffer(), b"hy there");
+        assert_eq!(data.media_type().as_str_repr(), "text/plain; charset=utf-8");
+    }
+
+    #[test]
+    fn fails_for_unregistered_iris() {
+        let loader = loader_with_text("mem:greeting", "hy there");
+        let source = source("mem:other", Default::default());
+
+        let err = assert_err!(loader.load_resource(&source, &test_context()).wait());
+        assert_eq!(err.kind(), ResourceLoadingErrorKind::NotFound);
+        assert_eq!(err.source_iri(), Some(&source.iri));
+    }
+
+    #[test]
+    fn respects_media_type_override() {
+        let loader = loader_with_text("mem:event", "BEGIN:VCALENDAR");
+        let media_type = MediaType::parse("text/calendar; method=REQUEST").unwrap();
+        let source = source("mem:event", UseMediaType::Override(media_type));
+
+        let enc_data = assert_ok!(loader.load_resource(&source, &test_context()).wait());
+        assert_eq!(enc_data.media_type().as_str_repr(), "text/calendar; method=REQUEST");
+    }
+
+    #[test]
+    fn unregister_removes_data() {
+        let loader = loader_with_text("mem:greeting", "hy there");
+        let iri = IRI::new("mem:greeting").unwrap();
+
+        assert!(loader.contains(&iri));
+        assert!(loader.unregister(&iri).is_some());
+        assert_not!(loader.contains(&iri));
+    }
+
+    #[test]
+    fn uses_transfer_encoding_hint_of_registered_data_if_not_overridden() {
+        use headers::header_components::TransferEncoding;
+        use ::resource::TransferEncodingHint;
+
+        let loader = MemResourceLoader::new();
+        let data = Data::plain_text("a=b", test_context().generate_content_id());
+        let meta = Metadata {
+            transfer_encoding_hint: TransferEncodingHint::UseBase64,
+            ..(**data.metadata()).clone()
+        };
+        loader.register(IRI::new("mem:table").unwrap(), Data::new(data.buffer().clone(), meta));
+
+        let mut source = source("mem:table", Default::default());
+        let enc_data = assert_ok!(loader.load_resource(&source, &test_context()).wait());
+        assert_eq!(enc_data.encoding(), TransferEncoding::Base64);
+
+        source.use_transfer_encoding = Some(TransferEncodingHint::UseQuotedPrintable);
+        let enc_data = assert_ok!(loader.load_resource(&source, &test_context()).wait());
+        assert_eq!(enc_data.encoding(), TransferEncoding::QuotedPrintable);
+    }
+}
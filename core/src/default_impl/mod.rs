@@ -7,14 +7,38 @@ mod cpupool;
 #[cfg(feature="default_impl_cpupool")]
 pub use self::cpupool::*;
 
+mod inline;
+pub use self::inline::*;
+
+#[cfg(feature="default_impl_tokio")]
+mod tokio_offloader;
+#[cfg(feature="default_impl_tokio")]
+pub use self::tokio_offloader::*;
+
 mod fs;
 pub use self::fs::*;
 
+mod mem;
+pub use self::mem::*;
+
+mod data_uri;
+pub use self::data_uri::*;
+
+mod cache;
+pub use self::cache::*;
+
+#[cfg(feature="default_impl_archive")]
+mod archive;
+#[cfg(feature="default_impl_archive")]
+pub use self::archive::*;
+
+mod mux;
+pub use self::mux::*;
+
 mod message_id_gen;
 pub use self::message_id_gen::*;
 
 
-#[cfg(all(feature="default_impl_cpupool"))]
 pub mod simple_context;
 
 #[cfg(all(test, not(feature="default_impl_cpupool")))]
@@ -24,19 +48,32 @@ compile_error!("test need following (default) features: default_impl_cpupool, de
 use soft_ascii_string::SoftAsciiString;
 #[cfg(test)]
 use headers::header_components::Domain;
+#[cfg(test)]
+use futures_cpupool::CpuPool;
+#[cfg(test)]
+use ::context::{CompositeContext, OffloaderComponent};
 
+/// A context which does not access the file system.
+///
+/// Resources can be made available by registering them with the
+/// `MemResourceLoader` (accessible through `ctx.resource_loader()`).
 #[cfg(test)]
-pub type TestContext = simple_context::Context;
+pub type TestContext = CompositeContext<MemResourceLoader, CpuPool, HashedIdGen>;
 
 //same crate so we can do this ;=)
 #[cfg(test)]
 pub fn test_context() -> TestContext {
-    //TODO crate a test context which does not access the file system
+    test_context_with_offloader(simple_cpu_pool())
+}
+
+/// Like `test_context` but using the given offloader.
+#[cfg(test)]
+pub fn test_context_with_offloader<O>(offloader: O)
+    -> CompositeContext<MemResourceLoader, O, HashedIdGen>
+    where O: OffloaderComponent
+{
     let domain = Domain::from_unchecked("fooblabar.test".to_owned());
     let unique_part = SoftAsciiString::from_unchecked("CM0U3c412");
-    simple_context::new(domain, unique_part).unwrap()
+    let id_gen = HashedIdGen::new(domain, unique_part).unwrap();
+    CompositeContext::new(MemResourceLoader::new(), offloader, id_gen)
 }
-
-
-
-
@@ -1,64 +1,271 @@
-use context::{OffloaderComponent, ResourceLoaderComponent};
-use utils::SendBoxFuture;
-use error::Error;
-use file_buffer::FileBuffer;
+use futures::IntoFuture;
 
-type BufferFuture = SendBoxFuture<FileBuffer, Error>;
-type DynResourceLoader =
-    ResourceLoaderComponent<BufferFuture=BufferFuture>;
+use ::{
+    iri::IRI,
+    utils::SendBoxFuture,
+    error::{
+        ResourceLoadingError,
+        ResourceLoadingErrorKind
+    },
+    resource::{
+        Data,
+        EncData,
+        Source
+    },
+    context::{
+        Context,
+        ResourceLoaderComponent
+    }
+};
 
-pub struct Mux {
-    lookup: HashMap<&'static str, Box<DynResourceLoader>>
+/// A `ResourceLoaderComponent` which dispatches to other loaders based on the IRI scheme.
+///
+/// As `ResourceLoaderComponent` is not object safe the `Mux` is a
+/// (statically typed) chain of loaders, which is terminated by
+/// `UnknownScheme`. Loaders are tried from the last added to the
+/// first added one, if non of them handles the scheme of the
+/// sources IRI loading fails with `ResourceLoadingErrorKind::UnknownScheme`.
+///
+/// `transfer_encode_resource` is dispatched based on the IRI of the
+/// `Source` the data was loaded from (see `Metadata::source`). Data without
+/// a source or with a source not handled by any loader is transfer encoded
+/// by the `UnknownScheme` at the end of the chain, which uses the default
+/// implementation. `use_preload` and `stop_using_preload` are dispatched
+/// based on the scheme of the preload IRI.
+///
+/// # Example
+///
+/// ```
+/// # extern crate mail_core;
+/// use mail_core::{
+///     default_impl::{FsResourceLoader, Mux},
+///     utils::Disabled
+/// };
+///
+/// # fn main() {
+/// let loader = Mux::new("path", FsResourceLoader::<Disabled>::new("./templates"))
+///     .with_handler("asset", FsResourceLoader::<Disabled>::new("./assets"));
+/// # let _ = loader;
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Mux<R, Next = UnknownScheme>
+    where R: ResourceLoaderComponent,
+          Next: ResourceLoaderComponent
+{
+    scheme: &'static str,
+    loader: R,
+    next: Next
 }
 
-impl Mux {
+impl<R> Mux<R>
+    where R: ResourceLoaderComponent
+{
+    /// Creates a new `Mux` using `loader` for IRIs with given scheme.
+    pub fn new(scheme: &'static str, loader: R) -> Self {
+        Mux { scheme, loader, next: UnknownScheme }
+    }
+}
 
-    pub fn set_handle<R>(&mut self, scheme: &'static str, handle: R)
-        where R: ResourceLoaderComponent
+impl<R, Next> Mux<R, Next>
+    where R: ResourceLoaderComponent,
+          Next: ResourceLoaderComponent
+{
+    /// Adds a loader for IRIs with given scheme.
+    ///
+    /// If a loader for the same scheme was already added it is
+    /// shadowed by the new loader.
+    pub fn with_handler<R2>(self, scheme: &'static str, loader: R2) -> Mux<R2, Self>
+        where R2: ResourceLoaderComponent
     {
-        //it would be awesome if we could specialize this for
-        // R: ResourceLoaderComponent<BufferFuture=BufferFuture>
-        // we actually should be able to using Monomorphization + if with branch elemination etc.
-        // let any: Box<Any> = Box::new(Option<R::BufferFutur>::None)
-        // any.downcast_ref::<Option<BufferFuture>>()
-        //    Ok() => self.lookup.insert(scheme, handle)
-        //    Err() => wrap => self.lookup.insert(scheme, wrap)
-        let wrapped = Wrapper { inner: handle };
-        let boxed: DynResourceLoader = Box::new(wrapped);
-        //TODO what happens with previous
-        self.lookup.insert(scheme, boxed);
+        Mux { scheme, loader, next: self }
+    }
+
+    /// The scheme handled by the last added loader.
+    pub fn scheme(&self) -> &'static str {
+        self.scheme
+    }
+
+    /// Returns a reference to the last added loader.
+    pub fn loader(&self) -> &R {
+        &self.loader
+    }
+
+    /// Returns a reference to the remaining chain of loaders.
+    pub fn next(&self) -> &Next {
+        &self.next
     }
 }
 
-impl ResourceLoaderComponent for Mux {
-    type BufferFuture = BufferFuture;
+impl<R, Next> ResourceLoaderComponent for Mux<R, Next>
+    where R: ResourceLoaderComponent,
+          Next: ResourceLoaderComponent
+{
+    fn load_resource(&self, source: &Source, ctx: &impl Context)
+        -> SendBoxFuture<EncData, ResourceLoadingError>
+    {
+        if source.iri.scheme().eq_ignore_ascii_case(self.scheme) {
+            self.loader.load_resource(source, ctx)
+        } else {
+            self.next.load_resource(source, ctx)
+        }
+    }
 
-    fn load_resource<O>( &self, source: &Source, offload: &O) -> Self::BufferFuture
-        where O: OffloaderComponent
+    fn transfer_encode_resource(&self, data: &Data, ctx: &impl Context)
+        -> SendBoxFuture<EncData, ResourceLoadingError>
     {
-        if let Some(handle) = self.lookup.get(source.source.scheme()) {
-            handle.load_resource(source, offload)
+        let handles_source = data.metadata().source.as_ref()
+            .map(|source| source.iri.scheme().eq_ignore_ascii_case(self.scheme))
+            .unwrap_or(false);
+
+        if handles_source {
+            self.loader.transfer_encode_resource(data, ctx)
+        } else {
+            self.next.transfer_encode_resource(data, ctx)
+        }
+    }
+
+    fn use_preload(&self, preload: &IRI) {
+        if preload.scheme().eq_ignore_ascii_case(self.scheme) {
+            self.loader.use_preload(preload)
         } else {
-            let err: Error = "unknow iri scheme".into();
-            Err(err).into_future().into_boxed()
+            self.next.use_preload(preload)
+        }
+    }
+
+    fn stop_using_preload(&self, preload: &IRI) {
+        if preload.scheme().eq_ignore_ascii_case(self.scheme) {
+            self.loader.stop_using_preload(preload)
+        } else {
+            self.next.stop_using_preload(preload)
         }
     }
 }
 
+/// The end of a `Mux` chain, which fails loading any resource.
+///
+/// Loading fails with `ResourceLoadingErrorKind::UnknownScheme`,
+/// transfer encoding uses the default implementation.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UnknownScheme;
 
-struct Wrapper<I: ResourceLoaderComponent> {
-    inner: I
+impl ResourceLoaderComponent for UnknownScheme {
+    fn load_resource(&self, source: &Source, _: &impl Context)
+        -> SendBoxFuture<EncData, ResourceLoadingError>
+    {
+        let err = ResourceLoadingError
+            ::from(ResourceLoadingErrorKind::UnknownScheme)
+            .with_source_iri_or_else(|| Some(source.iri.clone()));
+
+        Box::new(Err(err).into_future())
+    }
 }
 
-impl<I> ResourceLoaderComponent for Wrapper<I>
-    where I: ResourceLoaderComponent
-{
-    type BufferFuture = BufferFuture;
+#[cfg(test)]
+mod test {
+    use futures::Future;
+    use default_impl::test_context;
+    use ::resource::Metadata;
+    use super::*;
 
-    fn load_resource<O>( &self, source: &Source, offload: &O) -> Self::BufferFuture
-        where O: OffloaderComponent
+    /// Loader returning the given text for any IRI.
+    #[derive(Debug)]
+    struct TextLoader(&'static str);
+
+    impl ResourceLoaderComponent for TextLoader {
+        fn load_resource(&self, _: &Source, ctx: &impl Context)
+            -> SendBoxFuture<EncData, ResourceLoadingError>
+        {
+            let data = Data::plain_text(self.0, ctx.generate_content_id());
+            self.transfer_encode_resource(&data, ctx)
+        }
+    }
+
+    fn source(iri: &str) -> Source {
+        Source {
+            iri: IRI::new(iri).unwrap(),
+            use_media_type: Default::default(),
+            use_file_name: None,
+            use_transfer_encoding: None
+        }
+    }
+
+    fn load(mux: &impl ResourceLoaderComponent, iri: &str)
+        -> Result<EncData, ResourceLoadingError>
     {
-        let original = self.inner.load_resource(source, offload);
-        original.into_boxed()
+        mux.load_resource(&source(iri), &test_context()).wait()
+    }
+
+    #[test]
+    fn dispatches_based_on_scheme() {
+        let mux = Mux::new("path", TextLoader("path"))
+            .with_handler("mem", TextLoader("mem"));
+
+        let data = assert_ok!(load(&mux, "path:foo.txt"));
+        assert_eq!(&**assert_ok!(data.transfer_decode()).buffer(), b"path");
+
+        let data = assert_ok!(load(&mux, "mem:foo.txt"));
+        assert_eq!(&**assert_ok!(data.transfer_decode()).buffer(), b"mem");
+    }
+
+    #[test]
+    fn later_handlers_shadow_earlier_ones() {
+        let mux = Mux::new("path", TextLoader("first"))
+            .with_handler("path", TextLoader("second"));
+
+        let data = assert_ok!(load(&mux, "path:foo.txt"));
+        assert_eq!(&**assert_ok!(data.transfer_decode()).buffer(), b"second");
+    }
+
+    #[test]
+    fn fails_for_unknown_schemes() {
+        let mux = Mux::new("path", TextLoader("path"));
+
+        let err = assert_err!(load(&mux, "tpl:foo.txt"));
+        assert_eq!(err.kind(), ResourceLoadingErrorKind::UnknownScheme);
+        assert_eq!(err.source_iri(), Some(&IRI::new("tpl:foo.txt").unwrap()));
+    }
+
+    /// Loader which can't transfer encode anything.
+    #[derive(Debug)]
+    struct NoEncodeLoader;
+
+    impl ResourceLoaderComponent for NoEncodeLoader {
+        fn load_resource(&self, _: &Source, _: &impl Context)
+            -> SendBoxFuture<EncData, ResourceLoadingError>
+        {
+            panic!("load_resource is not used in this test")
+        }
+
+        fn transfer_encode_resource(&self, _: &Data, _: &impl Context)
+            -> SendBoxFuture<EncData, ResourceLoadingError>
+        {
+            let err = ResourceLoadingError::from(ResourceLoadingErrorKind::LoadingFailed);
+            Box::new(Err(err).into_future())
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn dispatches_transfer_encoding_based_on_data_source() {
+        let ctx = test_context();
+        let mux = Mux::new("path", TextLoader("path"))
+            .with_handler("noenc", NoEncodeLoader);
+
+        let data = Data::plain_text("hy", ctx.generate_content_id());
+        assert_ok!(mux.transfer_encode_resource(&data, &ctx).wait());
+
+        let with_source = |iri: &str| {
+            let metadata = Metadata {
+                source: Some(source(iri)),
+                ..(**data.metadata()).clone()
+            };
+            Data::new(data.buffer().clone(), metadata)
+        };
+
+        assert_ok!(mux.transfer_encode_resource(&with_source("path:hy.txt"), &ctx).wait());
+        assert_ok!(mux.transfer_encode_resource(&with_source("tpl:hy.txt"), &ctx).wait());
+
+        let err = assert_err!(mux.transfer_encode_resource(&with_source("noenc:hy.txt"), &ctx).wait());
+        assert_eq!(err.kind(), ResourceLoadingErrorKind::LoadingFailed);
+    }
+}
@@ -2,8 +2,10 @@
 //!
 //! It used the `FsResourceLoader` and `CpuPool` with a `CompositeContext`.
 //!
-//! Note this module is only available if the `default_impl_cpupool` feature
-//! is enabled.
+//! `simple_context::new` (and the `Context` alias) require the
+//! `default_impl_cpupool` feature. `simple_context::new_inline` (and the
+//! `InlineContext` alias) use the `InlineOffloader` instead of the `CpuPool`
+//! and are always available.
 //!
 //! # Example
 //!
@@ -29,13 +31,14 @@
 use std::io;
 
 use soft_ascii_string::SoftAsciiString;
+#[cfg(feature="default_impl_cpupool")]
 use futures_cpupool::{Builder, CpuPool};
 
 use internals::error::EncodingError;
 use headers::header_components::Domain;
 
-use ::context::CompositeContext;
-use ::default_impl::{FsResourceLoader, HashedIdGen};
+use ::context::{CompositeContext, OffloaderComponent};
+use ::default_impl::{FsResourceLoader, HashedIdGen, InlineOffloader};
 
 /// Error returned when creating a "simple_context" fails.
 #[derive(Debug, Fail)]
@@ -52,8 +55,12 @@ pub enum ContextSetupError {
 }
 
 /// Type Alias for a the type returned by `simple_context::new`.
+#[cfg(feature="default_impl_cpupool")]
 pub type Context = CompositeContext<FsResourceLoader, CpuPool, HashedIdGen>;
 
+/// Type Alias for a the type returned by `simple_context::new_inline`.
+pub type InlineContext = CompositeContext<FsResourceLoader, InlineOffloader, HashedIdGen>;
+
 /// create a new CompositeContext<FsResourceLoader, CpuPool, HashedIdGen>
 ///
 /// It uses the current working directory as root for the `FsResourceLoader`,
@@ -66,20 +73,40 @@ pub type Context = CompositeContext<FsResourceLoader, CpuPool, HashedIdGen>;
 /// or you create multiple contexts they should _not_ use the same `unique_part`
 /// under any circumstances (expect if they use different domains, but then you
 /// also should only use domain you actually own).
+#[cfg(feature="default_impl_cpupool")]
 pub fn new(domain: Domain, unique_part: SoftAsciiString) -> Result<Context, ContextSetupError> {
+    new_with_offloader(domain, unique_part, Builder::new().create())
+}
+
+/// create a new CompositeContext<FsResourceLoader, InlineOffloader, HashedIdGen>
+///
+/// This is like `simple_context::new` but runs all offloaded work on the
+/// thread polling the future using the `InlineOffloader`, which means it
+/// doesn't need the `default_impl_cpupool` feature.
+///
+/// The same constraints for `domain` and `unique_part` as for
+/// `simple_context::new` apply.
+pub fn new_inline(domain: Domain, unique_part: SoftAsciiString)
+    -> Result<InlineContext, ContextSetupError>
+{
+    new_with_offloader(domain, unique_part, InlineOffloader)
+}
+
+fn new_with_offloader<O>(domain: Domain, unique_part: SoftAsciiString, offloader: O)
+    -> Result<CompositeContext<FsResourceLoader, O, HashedIdGen>, ContextSetupError>
+    where O: OffloaderComponent
+{
     let resource_loader = FsResourceLoader
         ::with_cwd_root()
         .map_err(|err| ContextSetupError::ReadingEnv(err))?;
 
-    let cpu_pool = Builder::new().create();
-
     let id_gen = HashedIdGen
         ::new(domain, unique_part)
         .map_err(|err| ContextSetupError::PunyCodingDomain(err))?;
 
     Ok(CompositeContext::new(
         resource_loader,
-        cpu_pool,
+        offloader,
         id_gen,
     ))
-}
\ No newline at end of file
+}
@@ -0,0 +1,156 @@
+use std::{
+    io,
+    panic
+};
+
+use futures::Future;
+use futures03::{
+    FutureExt,
+    compat::{Compat, Future01CompatExt}
+};
+use tokio::{
+    runtime::{self, Handle, Runtime},
+    task::JoinError
+};
+
+use utils::SendBoxFuture;
+use context::OffloaderComponent;
+
+/// Creates a multi threaded tokio runtime meant to be used as compute pool.
+///
+/// The runtime uses `threads` worker threads, which bounds how much CPU
+/// heavy work (e.g. transfer encoding) is done concurrently.
+pub fn compute_runtime(threads: usize) -> Result<Runtime, io::Error> {
+    runtime::Builder::new_multi_thread()
+        .worker_threads(threads)
+        .thread_name("mail-compute")
+        .build()
+}
+
+/// A `OffloaderComponent` using tokio runtimes.
+///
+/// Work offloaded through `offload` (and `offload_fn`), like transfer
+/// encoding, is spawned on the compute runtime. Blocking functions
+/// offloaded through `offload_blocking_fn`, like reading a file, are run
+/// using `spawn_blocking` on the blocking runtime. Both handles can point
+/// to the same runtime, but it's recommended to use a dedicated compute
+/// runtime with a bounded number of threads (see `compute_runtime`).
+///
+/// If a offloaded task panics the panic is resumed when polling the
+/// returned future. If the runtime is shut down before the task completed
+/// (or the task is cancelled) the returned future fails with an `io::Error`
+/// of kind `Interrupted` converted into the error type of the future.
+#[derive(Debug, Clone)]
+pub struct TokioOffloader {
+    blocking: Handle,
+    compute: Handle
+}
+
+impl TokioOffloader {
+
+    /// Creates a new offloader from the handles of the blocking and compute runtime.
+    pub fn new(blocking: Handle, compute: Handle) -> Self {
+        TokioOffloader { blocking, compute }
+    }
+
+    /// Returns the handle of the runtime used for blocking functions.
+    pub fn blocking_handle(&self) -> &Handle {
+        &self.blocking
+    }
+
+    /// Returns the handle of the runtime used for CPU heavy work.
+    pub fn compute_handle(&self) -> &Handle {
+        &self.compute
+    }
+}
+
+impl OffloaderComponent for TokioOffloader {
+
+    fn offload<F>(&self, fut: F) -> SendBoxFuture<F::Item, F::Error>
+        where F: Future + Send + 'static,
+              F::Item: Send+'static,
+              F::Error: From<io::Error> + Send + 'static
+    {
+        let join_handle = self.compute.spawn(fut.compat());
+        Box::new(Compat::new(join_handle.map(flatten_join_result)))
+    }
+
+    fn offload_blocking_fn<FN, T, E>(&self, func: FN) -> SendBoxFuture<T, E>
+        where FN: FnOnce() -> Result<T, E> + Send + 'static,
+              T: Send + 'static,
+              E: From<io::Error> + Send + 'static
+    {
+        let join_handle = self.blocking.spawn_blocking(func);
+        Box::new(Compat::new(join_handle.map(flatten_join_result)))
+    }
+}
+
+/// Flattens the result of a joined task, resuming panics of the task.
+fn flatten_join_result<T, E>(result: Result<Result<T, E>, JoinError>) -> Result<T, E>
+    where E: From<io::Error>
+{
+    match result {
+        Ok(result) => result,
+        Err(err) => {
+            if err.is_panic() {
+                panic::resume_unwind(err.into_panic())
+            } else {
+                Err(io::Error::new(
+                    io::ErrorKind::Interrupted,
+                    "offloaded task was cancelled (e.g. the runtime was shut down)"
+                ).into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+    use futures::future;
+    use super::*;
+
+    fn offloader() -> (Runtime, Runtime, TokioOffloader) {
+        let blocking = runtime::Builder::new_multi_thread().build().unwrap();
+        let compute = compute_runtime(1).unwrap();
+        let offloader = TokioOffloader::new(blocking.handle().clone(), compute.handle().clone());
+        (blocking, compute, offloader)
+    }
+
+    fn thread_name() -> String {
+        thread::current().name().unwrap_or("").to_owned()
+    }
+
+    #[test]
+    fn offloads_futures_to_compute_runtime() {
+        let (_blocking, _compute, offloader) = offloader();
+        let res = offloader.offload(future::lazy(|| Ok::<_, io::Error>(thread_name()))).wait();
+        assert_eq!(assert_ok!(res), "mail-compute");
+    }
+
+    #[test]
+    fn offloads_blocking_fn_to_blocking_runtime() {
+        let (_blocking, _compute, offloader) = offloader();
+        let res = offloader.offload_blocking_fn(|| Ok::<_, io::Error>(thread_name())).wait();
+        assert_ne!(assert_ok!(res), "mail-compute");
+    }
+
+    #[test]
+    fn propagates_errors() {
+        let (_blocking, _compute, offloader) = offloader();
+        let res = offloader.offload_blocking_fn(|| {
+            Err::<(), _>(io::Error::new(io::ErrorKind::Other, "12"))
+        }).wait();
+        assert_eq!(assert_err!(res).to_string(), "12");
+    }
+
+    #[test]
+    fn fails_instead_of_panicking_if_the_runtime_shuts_down() {
+        let (blocking, compute, offloader) = offloader();
+        let fut = offloader.offload(future::empty::<(), io::Error>());
+        drop(blocking);
+        drop(compute);
+        let err = assert_err!(fut.wait());
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+    }
+}
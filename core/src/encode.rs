@@ -1,3 +1,13 @@
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    cmp,
+    io::Write,
+    sync::Arc
+};
+
+use futures::{Stream, Poll, Async};
+
 use soft_ascii_string::{
     SoftAsciiStr,
     SoftAsciiChar,
@@ -6,6 +16,7 @@ use soft_ascii_string::{
 use media_type::BOUNDARY;
 
 use internals::{
+    MailType,
     encoder::{
         EncodingBuffer, EncodingWriter,
     },
@@ -19,7 +30,9 @@ use headers::{
 };
 
 use ::{
+    MailBody::{SingleBody, MultipleBodies},
     error::MailError,
+    resource::{EncData, TransferEncodingHint, is_text_media_type},
     mail::{
         Mail,
         EncodableMail,
@@ -27,6 +40,286 @@ use ::{
     }
 };
 
+/// The maximal line length (excluding CRLF) allowed by RFC 5322.
+const LINE_LEN_HARD_LIMIT: usize = 998;
+
+/// The recommended maximal line length (excluding CRLF).
+const LINE_LEN_SOFT_LIMIT: usize = 78;
+
+/// A sink a mail is encoded into.
+///
+/// Headers, boundaries and similar are always written using a
+/// `EncodingBuffer`, but the (potentially large) transfer encoded
+/// bodies are passed in directly so that a sink doesn't need to
+/// copy them into a buffer.
+pub(crate) trait MailSink {
+
+    /// The mail type the mail is encoded with.
+    fn mail_type(&self) -> MailType;
+
+    /// Writes data (e.g. headers) through a encoding buffer.
+    fn with_buffer<FN>(&mut self, func: FN) -> Result<(), MailError>
+        where FN: FnOnce(&mut EncodingBuffer) -> Result<(), MailError>;
+
+    /// Writes a transfer encoded body.
+    ///
+    /// Like `EncodingBuffer::write_body_unchecked` a CRLF is added
+    /// if the body doesn't already end with one.
+    fn write_body(&mut self, body: &Arc<[u8]>) -> Result<(), MailError>;
+}
+
+impl MailSink for EncodingBuffer {
+
+    fn mail_type(&self) -> MailType {
+        EncodingBuffer::mail_type(self)
+    }
+
+    fn with_buffer<FN>(&mut self, func: FN) -> Result<(), MailError>
+        where FN: FnOnce(&mut EncodingBuffer) -> Result<(), MailError>
+    {
+        func(self)
+    }
+
+    fn write_body(&mut self, body: &Arc<[u8]>) -> Result<(), MailError> {
+        self.write_body_unchecked(body);
+        Ok(())
+    }
+}
+
+/// A `MailSink` streaming the encoded mail into a `io::Write` instance.
+///
+/// Headers are encoded into a (small) `EncodingBuffer` which is then
+/// written to the writer, bodies are written to the writer directly.
+pub(crate) struct WriterSink<'a, W: Write + 'a> {
+    writer: &'a mut W,
+    mail_type: MailType
+}
+
+impl<'a, W> WriterSink<'a, W>
+    where W: Write + 'a
+{
+    pub(crate) fn new(writer: &'a mut W, mail_type: MailType) -> Self {
+        WriterSink { writer, mail_type }
+    }
+}
+
+impl<'a, W> MailSink for WriterSink<'a, W>
+    where W: Write + 'a
+{
+    fn mail_type(&self) -> MailType {
+        self.mail_type
+    }
+
+    fn with_buffer<FN>(&mut self, func: FN) -> Result<(), MailError>
+        where FN: FnOnce(&mut EncodingBuffer) -> Result<(), MailError>
+    {
+        let mut buffer = EncodingBuffer::new(self.mail_type);
+        func(&mut buffer)?;
+        let bytes: Vec<u8> = buffer.into();
+        self.writer.write_all(&bytes)?;
+        Ok(())
+    }
+
+    fn write_body(&mut self, body: &Arc<[u8]>) -> Result<(), MailError> {
+        self.writer.write_all(body)?;
+        if !body.ends_with(b"\r\n") {
+            self.writer.write_all(b"\r\n")?;
+        }
+        Ok(())
+    }
+}
+
+/// A segment of a encoded mail.
+enum Segment {
+    /// Data written through a `EncodingBuffer` (e.g. headers).
+    Buffer(Vec<u8>),
+    /// A transfer encoded body (shared with the `EncData` it's from).
+    Body(Arc<[u8]>)
+}
+
+impl Segment {
+    fn as_bytes(&self) -> &[u8] {
+        match *self {
+            Segment::Buffer(ref buffer) => buffer,
+            Segment::Body(ref body) => body
+        }
+    }
+}
+
+/// A `MailSink` recording the encoded mail as a list of segments.
+///
+/// Bodies are not copied but their (`Arc`ed) buffer is shared.
+struct SegmentSink {
+    segments: VecDeque<Segment>,
+    mail_type: MailType
+}
+
+impl MailSink for SegmentSink {
+
+    fn mail_type(&self) -> MailType {
+        self.mail_type
+    }
+
+    fn with_buffer<FN>(&mut self, func: FN) -> Result<(), MailError>
+        where FN: FnOnce(&mut EncodingBuffer) -> Result<(), MailError>
+    {
+        let mut buffer = EncodingBuffer::new(self.mail_type);
+        func(&mut buffer)?;
+        self.segments.push_back(Segment::Buffer(buffer.into()));
+        Ok(())
+    }
+
+    fn write_body(&mut self, body: &Arc<[u8]>) -> Result<(), MailError> {
+        self.segments.push_back(Segment::Body(body.clone()));
+        if !body.ends_with(b"\r\n") {
+            self.segments.push_back(Segment::Buffer(b"\r\n".to_vec()));
+        }
+        Ok(())
+    }
+}
+
+/// A step of encoding a mail done by a `MailChunkStream`.
+enum EncodeStep {
+    /// Encode the headers and the body (or preamble) of the part at given path.
+    Part { path: Vec<usize>, top: bool },
+    /// Encode the delimiter in front of a sub-part of a multipart body.
+    Delimiter(SoftAsciiString),
+    /// Encode the close delimiter and epilogue of the multipart body at given path.
+    End { path: Vec<usize>, boundary: SoftAsciiString }
+}
+
+/// A `Stream` of chunks of a encoded mail.
+///
+/// This is returned by `EncodableMail::into_chunk_stream`, all chunks
+/// except the last one have exactly the requested chunk size.
+///
+/// The mail is encoded part by part while the stream is polled, i.e. each
+/// poll only encodes the parts (including re-encoding bodies if needed)
+/// required to fill the returned chunk. Transfer encoded bodies are not
+/// copied when encoding them, instead they are copied chunk by chunk into
+/// the returned chunks.
+pub struct MailChunkStream {
+    mail: EncodableMail,
+    chunk_size: usize,
+    /// The remaining steps, the next step is the last one.
+    steps: Vec<EncodeStep>,
+    /// The encoded but not yet returned segments.
+    sink: SegmentSink,
+    /// The offset into the first segment.
+    offset: usize
+}
+
+impl MailChunkStream {
+
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is 0.
+    pub(crate) fn new(mail: EncodableMail, mail_type: MailType, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunk size needs to be greater than 0");
+        MailChunkStream {
+            mail, chunk_size,
+            steps: vec![EncodeStep::Part { path: Vec::new(), top: true }],
+            sink: SegmentSink {
+                segments: VecDeque::new(),
+                mail_type
+            },
+            offset: 0
+        }
+    }
+
+    /// Encodes the next step into the sink.
+    ///
+    /// Returns `false` if there are no steps left.
+    fn encode_next_step(&mut self) -> Result<bool, MailError> {
+        let step =
+            match self.steps.pop() {
+                Some(step) => step,
+                None => return Ok(false)
+            };
+
+        match step {
+            EncodeStep::Part { path, top } => {
+                let mail = part_at(&self.mail, &path);
+                if let Some(boundary) = encode_part_start(mail, top, &mut self.sink)? {
+                    let nr_bodies =
+                        match mail.body() {
+                            MultipleBodies { ref bodies, .. } => bodies.len(),
+                            SingleBody { .. } => 0
+                        };
+
+                    self.steps.push(EncodeStep::End { path: path.clone(), boundary: boundary.clone() });
+                    for idx in (0..nr_bodies).rev() {
+                        let mut sub_path = path.clone();
+                        sub_path.push(idx);
+                        self.steps.push(EncodeStep::Part { path: sub_path, top: false });
+                        self.steps.push(EncodeStep::Delimiter(boundary.clone()));
+                    }
+                }
+            },
+            EncodeStep::Delimiter(boundary) => {
+                encode_delimiter(&boundary, &mut self.sink)?;
+            },
+            EncodeStep::End { path, boundary } => {
+                encode_part_end(part_at(&self.mail, &path), &boundary, &mut self.sink)?;
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Returns the (sub-)part of the mail at given path.
+fn part_at<'a>(mail: &'a Mail, path: &[usize]) -> &'a Mail {
+    path.iter().fold(mail, |mail, &idx| {
+        match mail.body() {
+            MultipleBodies { ref bodies, .. } => &bodies[idx],
+            SingleBody { .. } => panic!("[BUG] path points into a single body")
+        }
+    })
+}
+
+impl Stream for MailChunkStream {
+    type Item = Vec<u8>;
+    type Error = MailError;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let mut chunk = Vec::with_capacity(self.chunk_size);
+        while chunk.len() < self.chunk_size {
+            if self.sink.segments.is_empty() {
+                match self.encode_next_step() {
+                    Ok(true) => continue,
+                    Ok(false) => break,
+                    Err(err) => {
+                        // make sure the stream ends if polled again
+                        self.steps.clear();
+                        self.sink.segments.clear();
+                        return Err(with_mail_type(err, self.sink.mail_type));
+                    }
+                }
+            }
+
+            let segment_done = {
+                //UNWRAP_SAFE: we made sure there is a segment above
+                let segment = self.sink.segments.front().unwrap();
+                let rest = &segment.as_bytes()[self.offset..];
+                let take = cmp::min(rest.len(), self.chunk_size - chunk.len());
+                chunk.extend_from_slice(&rest[..take]);
+                self.offset += take;
+                take == rest.len()
+            };
+
+            if segment_done {
+                self.sink.segments.pop_front();
+                self.offset = 0;
+            }
+        }
+
+        if chunk.is_empty() {
+            Ok(Async::Ready(None))
+        } else {
+            Ok(Async::Ready(Some(chunk)))
+        }
+    }
+}
 
 ///
 /// # Panics
@@ -34,36 +327,223 @@ use ::{
 /// on `Mail` to prevent this from happening
 ///
 #[inline(always)]
-pub(crate) fn encode_mail(
+pub(crate) fn encode_mail<S>(
     mail: &EncodableMail,
     top: bool,
-    encoder: &mut EncodingBuffer
-) -> Result<(), MailError> {
-    _encode_mail(&*mail, top, encoder)
-        .map_err(|err| {
-            let mail_type = encoder.mail_type();
-            use self::MailError::*;
-
-            match err {
-                 Encoding(enc_err) => Encoding(enc_err.with_mail_type_or_else(||Some(mail_type))),
-                 other => other
+    sink: &mut S
+) -> Result<(), MailError>
+    where S: MailSink
+{
+    let mail_type = sink.mail_type();
+    _encode_mail(&*mail, top, sink)
+        .map_err(|err| with_mail_type(err, mail_type))
+}
+
+/// Adds the mail type to encoding errors.
+fn with_mail_type(err: MailError, mail_type: MailType) -> MailError {
+    use self::MailError::*;
+
+    match err {
+         Encoding(enc_err) => Encoding(enc_err.with_mail_type_or_else(||Some(mail_type))),
+         other => other
+    }
+}
+
+fn _encode_mail<S>(
+    mail: &Mail,
+    top: bool,
+    sink: &mut S
+) -> Result<(), MailError>
+    where S: MailSink
+{
+    if let Some(boundary) = encode_part_start(mail, top, sink)? {
+        if let MultipleBodies { ref bodies, .. } = *mail.body() {
+            for mail in bodies.iter() {
+                encode_delimiter(&boundary, sink)?;
+                _encode_mail(mail, false, sink)?;
             }
-        })
+        }
+        encode_part_end(mail, &boundary, sink)?;
+    }
+    Ok(())
 }
 
-fn _encode_mail(
+/// Encodes the headers and the body (single body) or the preamble (multipart body).
+///
+/// For multipart bodies the boundary is returned, the caller then has to
+/// encode each sub-part (prefixed with `encode_delimiter`) followed by
+/// `encode_part_end`.
+///
+/// # Panics
+/// if the body is not yet resolved use `Body::poll_body` or `IntoFuture`
+/// on `Mail` to prevent this from happening
+///
+fn encode_part_start<S>(
     mail: &Mail,
     top: bool,
-    encoder: &mut EncodingBuffer
-) -> Result<(), MailError> {
-    encode_headers(&mail, top, encoder)?;
+    sink: &mut S
+) -> Result<Option<SoftAsciiString>, MailError>
+    where S: MailSink
+{
+    let single_body = match mail.body() {
+        SingleBody { ref body } => {
+            let data = assume_encoded(body);
+            Some(encodable_data(data, sink.mail_type())?)
+        },
+        MultipleBodies { .. } => None
+    };
+    let single_body = single_body.as_ref().map(|data| &**data);
 
-    //the empty line between the headers and the body
-    encoder.write_blank_line();
+    sink.with_buffer(|encoder| {
+        encode_headers(&mail, single_body, top, encoder)?;
 
-    encode_mail_part(&mail, encoder)?;
+        //the empty line between the headers and the body
+        encoder.write_blank_line();
+        Ok(())
+    })?;
 
-    Ok(())
+    match mail.body() {
+        SingleBody { .. } => {
+            let data = single_body
+                .expect("[BUG] data of single body was not passed in");
+            sink.write_body(data.transfer_encoded_buffer())?;
+            Ok(None)
+        },
+        MultipleBodies { ref hidden_text, .. } => {
+            let boundary = multipart_boundary(mail)?;
+
+            if hidden_text.len() > 0 {
+                let preamble = prepare_multipart_text(hidden_text, &boundary)?;
+                sink.with_buffer(|encoder| {
+                    encoder.write_body_unchecked(&preamble.as_bytes());
+                    Ok(())
+                })?;
+            }
+            Ok(Some(boundary))
+        }
+    }
+}
+
+/// Returns the boundary of a (validated) multipart mail.
+fn multipart_boundary(mail: &Mail) -> Result<SoftAsciiString, MailError> {
+    let mail_was_validated_err_msg = "[BUG] mail was already validated";
+    let boundary = mail.headers()
+        .get_single(ContentType)
+        .expect(mail_was_validated_err_msg)
+        .expect(mail_was_validated_err_msg)
+        .get_param(BOUNDARY)
+        .expect(mail_was_validated_err_msg)
+        .to_content();
+
+    let boundary = SoftAsciiString
+        ::from_string(boundary)
+        .map_err(|orig_string| EncodingError
+            ::from(EncodingErrorKind::InvalidTextEncoding {
+                got_encoding: UTF_8,
+                expected_encoding: US_ASCII
+            })
+            .with_place_or_else(|| Some(Place::Header { name: "Content-Type" }))
+            .with_str_context(orig_string.into_source())
+        )?;
+
+    Ok(boundary)
+}
+
+/// Encodes the delimiter in front of a sub-part of a multipart body.
+fn encode_delimiter<S>(boundary: &SoftAsciiStr, sink: &mut S) -> Result<(), MailError>
+    where S: MailSink
+{
+    let minus = SoftAsciiChar::from_unchecked('-');
+    sink.with_buffer(|encoder| {
+        encoder.write_header_line(|handle| {
+            handle.write_char(minus)?;
+            handle.write_char(minus)?;
+            handle.write_str(boundary)
+        })?;
+        Ok(())
+    })
+}
+
+/// Encodes the close delimiter and the epilogue of a multipart body.
+fn encode_part_end<S>(mail: &Mail, boundary: &SoftAsciiStr, sink: &mut S) -> Result<(), MailError>
+    where S: MailSink
+{
+    let minus = SoftAsciiChar::from_unchecked('-');
+    let epilogue =
+        match mail.body() {
+            MultipleBodies { ref epilogue, .. } => epilogue,
+            SingleBody { .. } => panic!("[BUG] can not end a single body part")
+        };
+
+    sink.with_buffer(|encoder| {
+        encoder.write_header_line(|handle| {
+            handle.write_char(minus)?;
+            handle.write_char(minus)?;
+            handle.write_str(boundary)?;
+            handle.write_char(minus)?;
+            handle.write_char(minus)
+        })?;
+
+        if epilogue.len() > 0 {
+            let epilogue = prepare_multipart_text(epilogue, boundary)?;
+            encoder.write_body_unchecked(&epilogue.as_bytes());
+        }
+        Ok(())
+    })
+}
+
+/// Returns the data re-encoded if it's transfer encoding doesn't fit the mail type.
+///
+/// Bodies are normally transfer encoded before it is known with which
+/// `MailType` the mail will be encoded (e.g. if the server supports
+/// 8BITMIME). So data using the `8bit` encoding has to be re-encoded
+/// "on the fly" for `MailType::Ascii`. Data using the `binary` encoding
+/// is always re-encoded as it can't be send with any of the mail types.
+///
+/// Re-encoding uses `TransferEncodingHint::auto_for(mail_type)`. Text
+/// which was encoded with the `Auto` hint (which never uses `8bit`) is
+/// re-encoded this way, too, if the mail type supports 8bit bodies.
+fn encodable_data(data: &EncData, mail_type: MailType)
+    -> Result<Cow<EncData>, MailError>
+{
+    use headers::header_components::TransferEncoding::*;
+
+    let allows_8bit =
+        match mail_type {
+            MailType::Ascii => false,
+            MailType::Mime8BitEnabled | MailType::Internationalized => true
+        };
+
+    let used_auto_hint =
+        match data.metadata().transfer_encoding_hint {
+            TransferEncodingHint::Auto | TransferEncodingHint::NoHint => true,
+            _ => false
+        };
+
+    let needs_re_encoding =
+        match data.encoding() {
+            Binary => true,
+            _8Bit => !allows_8bit,
+            QuotedPrintable | Base64 => {
+                allows_8bit && used_auto_hint && is_text_media_type(data.media_type())
+            },
+            _ => false
+        };
+
+    if !needs_re_encoding {
+        return Ok(Cow::Borrowed(data));
+    }
+
+    let decoded = data.transfer_decode()?;
+    let re_encoded = decoded.transfer_encode(TransferEncodingHint::auto_for(mail_type));
+
+    let is_upgrade = data.encoding() == QuotedPrintable || data.encoding() == Base64;
+    if is_upgrade && re_encoded.encoding() != _8Bit {
+        // the text can't be send as is, keep the existing encoding
+        Ok(Cow::Borrowed(data))
+    } else {
+        Ok(Cow::Owned(re_encoded))
+    }
 }
 
 ///
@@ -73,11 +553,10 @@ fn _encode_mail(
 ///
 fn encode_headers(
     mail: &Mail,
+    single_body: Option<&EncData>,
     top: bool,
     encoder:  &mut EncodingBuffer
 ) -> Result<(), MailError> {
-    use super::MailBody::*;
-
     let mut handle = encoder.writer();
     if top {
         handle.write_str(SoftAsciiStr::from_unchecked(
@@ -100,15 +579,11 @@ fn encode_headers(
     }
 
 
-    match mail.body() {
-        SingleBody { ref body } => {
-            let data = assume_encoded(body);
-            let header = ContentTransferEncoding::body(data.encoding());
-            encode_header(&mut handle, header.name(), &header)?;
-            let header = ContentType::body(data.media_type().clone());
-            encode_header(&mut handle, header.name(), &header)?;
-        },
-        MultipleBodies { hidden_text:_, bodies:_ } => {}
+    if let Some(data) = single_body {
+        let header = ContentTransferEncoding::body(data.encoding());
+        encode_header(&mut handle, header.name(), &header)?;
+        let header = ContentType::body(data.media_type().clone());
+        encode_header(&mut handle, header.name(), &header)?;
     }
     Ok(())
 }
@@ -133,71 +608,90 @@ fn encode_header(
     })
 }
 
+/// Prepares the preamble/epilogue of a multipart body for writing it.
 ///
-/// # Panics
-/// if the body is not yet resolved use `Body::poll_body` or `IntoFuture`
-/// on `Mail` to prevent this from happening
-///
-fn encode_mail_part(mail: &Mail, encoder:  &mut EncodingBuffer )
-    -> Result<(), MailError>
+/// Line breaks are normalized to CRLF, and it is made sure that no line
+/// breaches the hard line length limit or could be mistaken for a
+/// boundary delimiter.
+fn prepare_multipart_text(text: &SoftAsciiStr, boundary: &SoftAsciiStr)
+    -> Result<String, EncodingError>
 {
-    use super::MailBody::*;
+    let mut out = String::with_capacity(text.len() + 2);
+    for line in text.as_str().lines() {
+        if line.len() > LINE_LEN_HARD_LIMIT {
+            return Err(EncodingError::from(EncodingErrorKind::HardLineLengthLimitBreached)
+                .with_place_or_else(|| Some(Place::Body)));
+        } else if line.len() > LINE_LEN_SOFT_LIMIT {
+            warn!("multipart preamble/epilogue line exceeds the soft line length limit");
+        }
 
-    let minus = SoftAsciiChar::from_unchecked('-');
+        let is_delimiter_like = line.starts_with("--")
+            && line[2..].starts_with(boundary.as_str());
+        if is_delimiter_like {
+            return Err(EncodingError::from(EncodingErrorKind::Malformed)
+                .with_place_or_else(|| Some(Place::Body))
+                .with_str_context(line));
+        }
 
-    match mail.body() {
-        SingleBody { ref body } => {
-            let data = assume_encoded(body);
-            let buffer = data.transfer_encoded_buffer();
-            encoder.write_body_unchecked(buffer);
-        },
-        MultipleBodies { ref hidden_text, ref bodies } => {
-            if hidden_text.len() > 0 {
-                //TODO find out if there is any source using the hidden text
-                // (e.g. for some form of validation, prove of senders validity etc.)
-                // if not drop the "hidden_text" field
-                warn!("\"hidden text\" in multipart bodies is dropped")
-            }
+        if !out.is_empty() {
+            out.push_str("\r\n");
+        }
+        out.push_str(line);
+    }
+    Ok(out)
+}
 
-            let mail_was_validated_err_msg = "[BUG] mail was already validated";
-            let boundary = mail.headers()
-                .get_single(ContentType)
-                .expect(mail_was_validated_err_msg)
-                .expect(mail_was_validated_err_msg)
-                .get_param(BOUNDARY)
-                .expect(mail_was_validated_err_msg)
-                .to_content();
-
-            let boundary = SoftAsciiString
-                ::from_string(boundary)
-                .map_err(|orig_string| EncodingError
-                    ::from(EncodingErrorKind::InvalidTextEncoding {
-                        got_encoding: UTF_8,
-                        expected_encoding: US_ASCII
-                    })
-                    .with_place_or_else(|| Some(Place::Header { name: "Content-Type" }))
-                    .with_str_context(orig_string.into_source())
-                )?;
+#[cfg(test)]
+mod test {
+    use futures::Future;
+    use soft_ascii_string::SoftAsciiStr;
+    use headers::headers::_From;
+    use default_impl::test_context;
+    use super::*;
 
-            for mail in bodies.iter() {
-                encoder.write_header_line(|handle| {
-                    handle.write_char(minus)?;
-                    handle.write_char(minus)?;
-                    handle.write_str(&*boundary)
-                })?;
-                _encode_mail(mail, false, encoder)?;
-            }
+    #[test]
+    fn multipart_text_line_breaks_are_normalized() {
+        let text = SoftAsciiStr::from_unchecked("line one\nline two\r\nline three");
+        let boundary = SoftAsciiStr::from_unchecked("=_^0.abc");
+        let prepared = assert_ok!(prepare_multipart_text(text, boundary));
+        assert_eq!(prepared, "line one\r\nline two\r\nline three");
+    }
 
-            if bodies.len() > 0 {
-                encoder.write_header_line(|handle| {
-                    handle.write_char(minus)?;
-                    handle.write_char(minus)?;
-                    handle.write_str(&*boundary)?;
-                    handle.write_char(minus)?;
-                    handle.write_char(minus)
-                })?;
-            }
-        }
+    #[test]
+    fn multipart_text_containing_the_boundary_is_rejected() {
+        let text = SoftAsciiStr::from_unchecked("hy\n--=_^0.abc\nthere");
+        let boundary = SoftAsciiStr::from_unchecked("=_^0.abc");
+        assert_err!(prepare_multipart_text(text, boundary));
     }
-    Ok(())
+
+    #[test]
+    fn multipart_text_with_too_long_lines_is_rejected() {
+        let text = "a".repeat(999);
+        let text = SoftAsciiStr::from_unchecked(&text);
+        let boundary = SoftAsciiStr::from_unchecked("=_^0.abc");
+        assert_err!(prepare_multipart_text(text, boundary));
+    }
+
+    test!(chunk_stream_encodes_the_mail_part_by_part, {
+        let ctx = test_context();
+        let mut mail = Mail::plain_text("r9", &ctx)
+            .wrap_with_mixed(vec![Mail::plain_text("r8", &ctx), Mail::plain_text("r7", &ctx)]);
+        mail.insert_headers(headers! {
+            _From: ["random@this.is.no.mail"]
+        }?);
+
+        let enc_mail = assert_ok!(mail.into_encodable_mail(ctx).wait());
+        let expected = enc_mail.encode_into_bytes(MailType::Ascii)?;
+
+        let mut stream = MailChunkStream::new(enc_mail, MailType::Ascii, 4);
+        let first = match stream.poll()? {
+            Async::Ready(Some(chunk)) => chunk,
+            _ => panic!("expected a chunk")
+        };
+        // only the headers of the outer mail are encoded
+        assert!(stream.steps.len() > 1);
+
+        let rest = stream.collect().wait()?;
+        assert_eq!([vec![first], rest].concat().concat(), expected);
+    });
 }
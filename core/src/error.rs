@@ -58,7 +58,31 @@ pub enum ResourceLoadingErrorKind {
     LoadingFailed,
 
     #[fail(display = "automatically detecting the media type failed")]
-    MediaTypeDetectionFailed
+    MediaTypeDetectionFailed,
+
+    /// There is no resource loader for the scheme of the sources IRI.
+    #[fail(display = "no resource loader for the IRI scheme")]
+    UnknownScheme,
+
+    /// Loading the resource is not allowed.
+    ///
+    /// E.g. because the path it refers to escapes the root of a
+    /// confined `FsResourceLoader`.
+    #[fail(display = "loading the resource is forbidden")]
+    Forbidden,
+
+    /// The IRI is not valid for the resource loader handling it's scheme.
+    ///
+    /// E.g. a `data:` IRI without a `,` or with invalid base64 data.
+    #[fail(display = "malformed IRI")]
+    MalformedIRI,
+
+    /// Loading the resource didn't complete in time.
+    ///
+    /// See `LoadingLimits` for the timeouts used when loading
+    /// the resources of a mail.
+    #[fail(display = "loading the resource timed out")]
+    Timeout
 }
 
 /// The loading of an Resource failed.
@@ -151,6 +175,91 @@ impl From<io::Error> for ResourceLoadingError {
     }
 }
 
+/// A resource of a mail which failed to load.
+#[derive(Debug)]
+pub struct ResourceLoadingFailure {
+    /// The position of the resource in the mime tree of the mail.
+    ///
+    /// Each element is the (0-based) index of a body in a multipart body,
+    /// e.g. `[1, 0]` is the first body of the second body of the mail while
+    /// `[]` is the body of a singlepart mail.
+    pub position: Vec<usize>,
+
+    /// The error loading the resource failed with.
+    ///
+    /// Use `ResourceLoadingError::source_iri` to get the IRI of the
+    /// resource (if it has one).
+    pub error: ResourceLoadingError
+}
+
+impl ResourceLoadingFailure {
+
+    /// Returns the position as dotted MIME part number, e.g. `2.1`.
+    ///
+    /// Like with IMAP part numbers this is 1-based, the body of a
+    /// singlepart mail is part `1`.
+    pub fn mime_path(&self) -> String {
+        if self.position.is_empty() {
+            return "1".to_owned();
+        }
+        let parts = self.position.iter()
+            .map(|idx| (idx + 1).to_string())
+            .collect::<Vec<_>>();
+        parts.join(".")
+    }
+}
+
+/// All resources of a mail which failed to load.
+///
+/// See `MailFutureBuilder::collect_all_errors`.
+#[derive(Debug)]
+pub struct ResourceLoadingErrors {
+    failures: Vec<ResourceLoadingFailure>
+}
+
+impl ResourceLoadingErrors {
+
+    /// Creates a new instance from the failures.
+    ///
+    /// # Panics
+    ///
+    /// If `failures` is empty.
+    pub fn new(failures: Vec<ResourceLoadingFailure>) -> Self {
+        assert!(!failures.is_empty(), "ResourceLoadingErrors requires at least one failure");
+        ResourceLoadingErrors { failures }
+    }
+
+    /// The failures in the order the resources appear in the mail.
+    pub fn failures(&self) -> &[ResourceLoadingFailure] {
+        &self.failures
+    }
+
+    /// Turns this instance into the contained failures.
+    pub fn into_failures(self) -> Vec<ResourceLoadingFailure> {
+        self.failures
+    }
+}
+
+impl Display for ResourceLoadingErrors {
+    fn fmt(&self, fter: &mut fmt::Formatter) -> fmt::Result {
+        write!(fter, "loading {} resource(s) failed:", self.failures.len())?;
+        for failure in self.failures.iter() {
+            write!(fter, " {}", failure.mime_path())?;
+            if let Some(iri) = failure.error.source_iri() {
+                write!(fter, " ({})", iri.as_str())?;
+            }
+            write!(fter, ": {};", failure.error)?;
+        }
+        Ok(())
+    }
+}
+
+impl Fail for ResourceLoadingErrors {
+    fn cause(&self) -> Option<&Fail> {
+        Some(&self.failures[0].error)
+    }
+}
+
 
 #[derive(Debug, Fail)]
 pub enum OtherValidationError {
@@ -176,13 +285,28 @@ pub enum OtherValidationError {
     #[fail(display = "inserting Content-Type for singlepart body is not allowed")]
     InsertSinglepartContentTypeHeader,
 
+    /// The media type of a mail build with a `MultipartBuilder` is given to
+    /// `Builder::multipart`, so a `Content-Type` header can not be added.
+    #[fail(display = "Content-Type header given for multipart mail build with a media type")]
+    MultipartContentTypeHeaderGiven,
+
     /// A multipart mail requires a `Content-Type` header to be given.
     #[fail(display = "multipart mail does not contain a content type header")]
     MissingContentTypeHeader,
 
     /// A mail (top level, not in multipart) requires a `From` header to be given.
     #[fail(display = "mail did not contain a From header")]
-    NoFrom
+    NoFrom,
+
+    /// A operation only possible for multipart mails was used on a non multipart mail.
+    ///
+    /// E.g. setting the preamble of a singlepart mail.
+    #[fail(display = "mail does not have a multipart body")]
+    NotMultipart,
+
+    /// A multipart mail has to contain at least one body.
+    #[fail(display = "multipart mail without any body")]
+    EmptyMultipartBody
 }
 
 impl From<OtherValidationError> for HeaderValidationError {
@@ -199,6 +323,38 @@ impl From<OtherValidationError> for MailError {
     }
 }
 
+/// Reasons why parsing a (encoded) mail can fail.
+#[derive(Copy, Clone, Debug, Fail, PartialEq, Eq, Hash)]
+pub enum ParsingError {
+    /// A header line is neither a `name: value` line nor a folded continuation line.
+    #[fail(display = "malformed header field")]
+    MalformedHeader,
+
+    /// A header contained data which is not valid utf-8.
+    #[fail(display = "header field is not valid utf-8")]
+    InvalidHeaderEncoding,
+
+    /// The `Content-Type` header could not be parsed.
+    #[fail(display = "invalid Content-Type header")]
+    InvalidContentType,
+
+    /// The `Content-Transfer-Encoding` header has a unknown/invalid value.
+    #[fail(display = "invalid Content-Transfer-Encoding header")]
+    InvalidContentTransferEncoding,
+
+    /// The `Content-Disposition` header could not be parsed.
+    #[fail(display = "invalid Content-Disposition header")]
+    InvalidContentDisposition,
+
+    /// A multipart content type did not have a boundary parameter.
+    #[fail(display = "multipart Content-Type without boundary parameter")]
+    MissingBoundary,
+
+    /// A multipart body was not terminated by a closing delimiter.
+    #[fail(display = "multipart body without closing boundary delimiter")]
+    MissingClosingBoundary
+}
+
 /// General Error combining most other error wrt. mail creation and encoding.
 #[derive(Debug, Fail)]
 pub enum MailError {
@@ -227,7 +383,22 @@ pub enum MailError {
     /// E.g. the file to attach or the image to embedded could not
     /// be found.
     #[fail(display = "{}", _0)]
-    ResourceLoading(ResourceLoadingError)
+    ResourceLoading(ResourceLoadingError),
+
+    /// Loading one or more resources failed.
+    ///
+    /// This is only returned by a `MailFuture` using `collect_all_errors`,
+    /// in which case it lists all resources which failed to load.
+    #[fail(display = "{}", _0)]
+    ResourcesLoading(ResourceLoadingErrors),
+
+    /// Parsing a mail failed.
+    #[fail(display = "{}", _0)]
+    Parsing(ParsingError),
+
+    /// Writing the encoded mail failed due to an I/O-Error.
+    #[fail(display = "{}", _0)]
+    Io(io::Error)
 }
 
 impl From<BuildInValidationError> for MailError {
@@ -276,13 +447,22 @@ impl From<ComponentCreationError> for MailError {
     }
 }
 
+impl From<ParsingError> for MailError {
+    fn from(err: ParsingError) -> Self {
+        MailError::Parsing(err)
+    }
+}
+
+impl From<io::Error> for MailError {
+    fn from(err: io::Error) -> Self {
+        MailError::Io(err)
+    }
+}
+
 
 /// Error returned when trying to _unload_ and `Resource` and it fails.
 #[derive(Copy, Clone, Debug, Fail)]
 pub enum ResourceNotUnloadableError {
-    /// The resource can not be unloaded because its in use.
-    #[fail(display = "resource is in use, can't unload it")]
-    InUse,
     /// The resource can not be unloaded because it doesn't has a source.
     ///
     /// Which means if we would unload it we could not reload it. Note
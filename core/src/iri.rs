@@ -62,6 +62,9 @@ impl IRI {
 
     /// crates a new a IRI
     ///
+    /// The given string is reused as buffer, i.e. if a `String` is passed
+    /// in the (potentially long) tail is not copied.
+    ///
     /// 1. this determines the first occurrence of `:` to split the input into scheme and tail
     /// 2. it validates that the scheme name is [RFC 3986](https://tools.ietf.org/html/rfc3986)
     ///    compatible, i.e. is ascii, starting with a letter followed by alpha numeric characters
@@ -193,6 +196,17 @@ impl<'de> Deserialize<'de> for IRI {
 
                 Ok(iri)
             }
+
+            // IRI's can be quite long (e.g. `data:` IRI's) so avoid copying them
+            fn visit_string<E>(self, s: String) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                let iri = IRI::new(s)
+                    .map_err(|err| E::custom(err))?;
+
+                Ok(iri)
+            }
         }
 
         deserializer.deserialize_str(IRIVisitor)
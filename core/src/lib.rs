@@ -13,9 +13,13 @@ extern crate failure;
 extern crate mime as media_type;
 extern crate chrono;
 extern crate futures;
+#[cfg(feature="loading_timeouts")]
+extern crate futures_timer;
 extern crate rand;
 extern crate vec1;
 extern crate soft_ascii_string;
+#[cfg(unix)]
+extern crate libc;
 
 #[cfg(feature="serde")]
 extern crate serde;
@@ -25,10 +29,21 @@ extern crate serde_test;
 #[cfg(feature="default_impl_cpupool")]
 extern crate futures_cpupool;
 
+#[cfg(feature="std-future")]
+extern crate futures03;
+#[cfg(feature="default_impl_tokio")]
+extern crate tokio;
+
+#[cfg(feature="default_impl_archive")]
+extern crate zip;
+#[cfg(feature="default_impl_archive")]
+extern crate tar;
+#[cfg(feature="default_impl_archive")]
+extern crate flate2;
+
 extern crate mail_internals as common;
 #[cfg_attr(test, macro_use)]
 extern crate mail_headers as headers;
-extern crate checked_command;
 
 
 #[macro_use]
@@ -41,13 +56,19 @@ pub mod context;
 mod resource;
 mod encode;
 mod mail;
+pub mod parse;
+mod builder;
 pub mod compose;
+#[cfg(feature="std-future")]
+pub mod std_future;
 
 pub mod default_impl;
 
 pub use self::iri::IRI;
 pub use self::resource::*;
 pub use self::mail::*;
+pub use self::builder::*;
+pub use self::encode::MailChunkStream;
 
 pub use ::context::Context;
 
@@ -4,16 +4,16 @@
 
 use std::{
     ops::Deref,
+    io::Write,
+    time::Duration,
     fmt,
     mem
 };
 
 use soft_ascii_string::SoftAsciiString;
+use vec1::Vec1;
 use futures::{
-    future::{
-        self,
-        Either
-    },
+    future,
     Future,
     Async,
     Poll
@@ -42,16 +42,24 @@ use headers::{
     }
 };
 
+#[cfg(feature="loading_timeouts")]
+use futures_timer::Delay;
+
 use ::{
+    IRI,
     utils::SendBoxFuture,
+    encode::{WriterSink, MailChunkStream},
     mime::create_structured_random_boundary,
     error::{
         MailError,
         OtherValidationError,
-        ResourceLoadingError
+        ResourceLoadingError,
+        ResourceLoadingErrorKind,
+        ResourceLoadingErrors,
+        ResourceLoadingFailure
     },
     resource::*,
-    context::Context
+    context::{Context, LoadingLimits}
 };
 
 /// A type representing a Mail.
@@ -124,19 +132,23 @@ use ::{
 ///     headers::*,
 /// #   header_components::Domain
 /// };
-/// use mail_core::{Mail,  Resource};
+/// use mail_core::{Builder, Resource};
 /// # use mail_core::default_impl::simple_context;
 ///
 /// # fn main() {
 /// # let domain = Domain::from_unchecked("example.com".to_owned());
 /// # let ctx = simple_context::new(domain, "xqi93".parse().unwrap()).unwrap();
 /// let resource = Resource::plain_text("Hy there!", &ctx);
-/// let mut mail = Mail::new_singlepart_mail(resource);
-/// mail.insert_headers(headers! {
-///     _From: [("I'm Awesome", "bla@examle.com")],
-///     _To: ["unknow@example.com"],
-///     Subject: "Hy there message"
-/// }.unwrap());
+/// let mail = Builder::singlepart(resource)
+///     .headers(headers! {
+///         _From: [("I'm Awesome", "bla@examle.com")],
+///         _To: ["unknow@example.com"],
+///         Subject: "Hy there message"
+///     }.unwrap())
+///     .unwrap()
+///     .build()
+///     .unwrap();
+/// # let _ = mail;
 /// # }
 /// ```
 ///
@@ -154,7 +166,7 @@ use ::{
 /// #       Domain,
 ///     }
 /// };
-/// use mail_core::{Mail, Resource};
+/// use mail_core::{Mail, Builder};
 /// # use mail_core::default_impl::simple_context;
 ///
 /// # fn main() {
@@ -165,12 +177,19 @@ use ::{
 ///
 /// // This will generate `multipart/x.made-up-think; boundary=randome_generate_boundary`
 /// let media_type = MediaType::new("multipart", "x.made-up-thing").unwrap();
-/// let mut mail = Mail::new_multipart_mail(media_type, vec![sub_body1, sub_body2]);
-/// mail.insert_headers(headers! {
-///     _From: [("I'm Awesome", "bla@examle.com")],
-///     _To: ["unknow@example.com"],
-///     Subject: "Hy there message"
-/// }.unwrap());
+/// let mail = Builder::multipart(media_type)
+///     .unwrap()
+///     .body(sub_body1)
+///     .body(sub_body2)
+///     .headers(headers! {
+///         _From: [("I'm Awesome", "bla@examle.com")],
+///         _To: ["unknow@example.com"],
+///         Subject: "Hy there message"
+///     }.unwrap())
+///     .unwrap()
+///     .build()
+///     .unwrap();
+/// # let _ = mail;
 /// # }
 /// ```
 #[derive(Clone, Debug)]
@@ -191,14 +210,21 @@ pub enum MailBody {
         body: Resource
     },
     MultipleBodies {
-        //TODO[now]: use Vec1
-        bodies: Vec<Mail>,
-        /// This is part of the standard! But we won't
-        /// make it public available for now. Through
-        /// there is a chance that we need to do so
-        /// in the future as some mechanisms might
-        /// misuse this, well unusual think.
-        hidden_text: SoftAsciiString
+        /// The bodies of the multipart body.
+        ///
+        /// A multipart body always contains at least one body.
+        bodies: Vec1<Mail>,
+        /// The multipart preamble.
+        ///
+        /// This text is placed before the first boundary delimiter and is
+        /// normally not displayed by mail clients, e.g. the classic
+        /// "This is a multi-part message in MIME format.".
+        hidden_text: SoftAsciiString,
+        /// The multipart epilogue.
+        ///
+        /// This text is placed after the closing boundary delimiter and
+        /// is normally not displayed by mail clients.
+        epilogue: SoftAsciiString
     }
 }
 
@@ -230,14 +256,18 @@ impl Mail {
     /// type (when encoding the mail) it is not required nor expected to have the
     /// boundary parameter. The boundary will always be automatically generated
     /// independently of wether or not it was passed as media type.
-    pub fn new_multipart_mail(content_type: MediaType, bodies: Vec<Mail>) -> Self {
+    ///
+    /// As a multipart body can not be empty `bodies` is a `Vec1`, use
+    /// `Vec1::try_from_vec` or the `MultipartBuilder` if you have a `Vec`.
+    pub fn new_multipart_mail(content_type: MediaType, bodies: Vec1<Mail>) -> Self {
         let mut headers = HeaderMap::new();
         headers.insert(ContentType::body(content_type));
         Mail {
             headers,
             body: MailBody::MultipleBodies {
                 bodies,
-                hidden_text: SoftAsciiString::new()
+                hidden_text: SoftAsciiString::new(),
+                epilogue: SoftAsciiString::new()
             }
         }
     }
@@ -272,6 +302,53 @@ impl Mail {
         self.headers_mut().insert_all(headers);
     }
 
+    /// Sets the preamble of a mail with a multipart body.
+    ///
+    /// The preamble is written before the first boundary delimiter
+    /// and is normally not displayed by mail clients.
+    ///
+    /// It must neither contain a line starting with the boundary delimiter
+    /// nor lines longer than the hard line length limit, this is checked
+    /// when encoding the mail.
+    ///
+    /// # Error
+    ///
+    /// Fails with `OtherValidationError::NotMultipart` if the mail does
+    /// not have a multipart body.
+    pub fn set_preamble(&mut self, preamble: SoftAsciiString)
+        -> Result<(), OtherValidationError>
+    {
+        match self.body {
+            MailBody::MultipleBodies { ref mut hidden_text, .. } => {
+                *hidden_text = preamble;
+                Ok(())
+            },
+            MailBody::SingleBody { .. } => Err(OtherValidationError::NotMultipart)
+        }
+    }
+
+    /// Sets the epilogue of a mail with a multipart body.
+    ///
+    /// The epilogue is written after the closing boundary delimiter
+    /// and is normally not displayed by mail clients. The same
+    /// constraints as for `set_preamble` apply.
+    ///
+    /// # Error
+    ///
+    /// Fails with `OtherValidationError::NotMultipart` if the mail does
+    /// not have a multipart body.
+    pub fn set_epilogue(&mut self, epilogue: SoftAsciiString)
+        -> Result<(), OtherValidationError>
+    {
+        match self.body {
+            MailBody::MultipleBodies { epilogue: ref mut current, .. } => {
+                *current = epilogue;
+                Ok(())
+            },
+            MailBody::SingleBody { .. } => Err(OtherValidationError::NotMultipart)
+        }
+    }
+
     /// Returns a reference to the currently set headers.
     ///
     /// Note that some headers namely `Content-Transfer-Encoding` as well
@@ -313,6 +390,9 @@ impl Mail {
     ///   for more details)
     /// - doing this recursively with all contained mails
     ///
+    /// That multipart bodies are not empty doesn't need to be checked
+    /// as it's enforced by `MailBody::MultipleBodies` using a `Vec1`.
+    ///
     /// Note that this will be called by `into_encodable_mail`, therefor
     /// it is normally not required to call this function by yourself.
     ///
@@ -329,7 +409,7 @@ impl Mail {
         match self.body() {
             &MailBody::SingleBody { .. } => {},
             &MailBody::MultipleBodies { ref bodies, .. } => {
-                for body in bodies {
+                for body in bodies.iter() {
                     body.generally_validate_mail()?;
                 }
             }
@@ -351,6 +431,8 @@ impl Mail {
     ///      replacing the old resource instances with the new loaded and
     ///      encoded ones once all of them had been loaded (and encoded)
     ///      successfully.
+    ///    - The number of concurrent loads and the time loading can take
+    ///      are limited by the contexts `LoadingLimits`.
     ///
     /// 3. Insert all auto generated headers (like e.g. `Date`).
     ///
@@ -363,7 +445,56 @@ impl Mail {
     /// are never loaded from disk.
     ///
     pub fn into_encodable_mail<C: Context>(self, ctx: C) -> MailFuture<C> {
-        MailFuture::new(self, ctx)
+        self.into_encodable_mail_builder(ctx).build()
+    }
+
+    /// Like `into_encodable_mail` but allows setting additional options.
+    ///
+    /// See `MailFutureBuilder` for the available options.
+    pub fn into_encodable_mail_builder<C: Context>(self, ctx: C) -> MailFutureBuilder<C> {
+        MailFutureBuilder {
+            mail: self, ctx,
+            preloads: Vec::new(),
+            collect_all_errors: false
+        }
+    }
+
+    /// Like `into_encodable_mail` but blocks until the mail is encodable.
+    ///
+    /// This is mainly meant for CLI tools and tests, in combination with
+    /// the `InlineOffloader` no thread pool is needed at all.
+    ///
+    /// As this blocks the current thread it must not be called from
+    /// a future polled by an event loop.
+    ///
+    /// # Deadlocks
+    ///
+    /// This is only safe if the offloader used by `ctx` does not need the
+    /// calling thread to make progress, e.g. the `InlineOffloader` or a
+    /// thread pool like `CpuPool`. Using it with an offloader which spawns
+    /// onto an executor driven by the current thread (like a current-thread
+    /// tokio runtime) will block forever.
+    pub fn into_encodable_mail_blocking<C: Context>(self, ctx: C)
+        -> Result<EncodableMail, MailError>
+    {
+        self.into_encodable_mail(ctx).wait()
+    }
+
+    /// Unloads all resources of the mail which can be unloaded.
+    ///
+    /// Resources which can not be unloaded (see `Resource::unload`)
+    /// are kept as they are. Unloaded resources will be reloaded
+    /// when the mail is turned into an encodable mail.
+    ///
+    /// Returns the number of resources which had been unloaded.
+    pub fn unload_resources(&mut self) -> usize {
+        let mut unloaded = 0;
+        self.visit_mail_bodies_mut(&mut |resource: &mut Resource| {
+            if !resource.is_source() && resource.unload().is_ok() {
+                unloaded += 1;
+            }
+        });
+        unloaded
     }
 
     /// Visit all mail bodies, the visiting order is deterministic.
@@ -383,12 +514,33 @@ impl Mail {
             SingleBody { ref  body } =>
                 use_it_fn(body),
             MultipleBodies { ref  bodies, .. } =>
-                for body in bodies {
+                for body in bodies.iter() {
                     body.visit_mail_bodies(use_it_fn)
                 }
         }
     }
 
+    /// Visit all mail bodies passing in their position in the mime tree.
+    ///
+    /// The position is a path of (0-based) body indices, see
+    /// `ResourceLoadingFailure::position`. The visiting order is the
+    /// same as the one of `visit_mail_bodies`.
+    fn visit_mail_bodies_with_position<FN>(&self, position: &mut Vec<usize>, use_it_fn: &mut FN)
+        where FN: FnMut(&[usize], &Resource)
+    {
+        use self::MailBody::*;
+        match self.body {
+            SingleBody { ref body } =>
+                use_it_fn(position, body),
+            MultipleBodies { ref bodies, .. } =>
+                for (idx, body) in bodies.iter().enumerate() {
+                    position.push(idx);
+                    body.visit_mail_bodies_with_position(position, use_it_fn);
+                    position.pop();
+                }
+        }
+    }
+
     /// Visit all mail bodies, the visiting order is deterministic.
     ///
     /// See `visit_mail_bodies` for a listing of **visiting order guarantees** given
@@ -401,7 +553,7 @@ impl Mail {
             SingleBody { ref mut body } =>
                 use_it_fn(body),
             MultipleBodies { ref mut bodies, .. } =>
-                for body in bodies {
+                for body in bodies.iter_mut() {
                     body.visit_mail_bodies_mut(use_it_fn)
                 }
         }
@@ -427,23 +579,87 @@ pub struct MailFuture<C: Context> {
 }
 
 enum InnerMailFuture<C: Context> {
-    New { mail: Mail, ctx: C },
+    New { mail: Mail, ctx: C, preloads: Vec<IRI>, collect_all_errors: bool },
     Loading {
         mail: Mail,
-        pending: future::JoinAll<Vec<Either<
-            SendBoxFuture<EncData, ResourceLoadingError>,
-            future::FutureResult<EncData, ResourceLoadingError>
-        >>>,
-        ctx: C
+        pending: LoadResources<C>,
+        ctx: C,
+        _preloads: PreloadScope<C>
     },
     Poison
 }
 
-impl<C> MailFuture<C>
+/// Builder for a `MailFuture` with non default options.
+///
+/// Created through `Mail::into_encodable_mail_builder`.
+pub struct MailFutureBuilder<C: Context> {
+    mail: Mail,
+    ctx: C,
+    preloads: Vec<IRI>,
+    collect_all_errors: bool
+}
+
+impl<C> MailFutureBuilder<C>
     where C: Context
 {
-    fn new(mail: Mail, ctx: C) -> Self {
-        MailFuture { inner: InnerMailFuture::New { mail, ctx } }
+    /// Loads all resources even if loading some of them fails.
+    ///
+    /// By default the future fails with `MailError::ResourceLoading` as
+    /// soon as the first resource fails to load. With this option all
+    /// resources are loaded and if any of them failed the future fails
+    /// with `MailError::ResourcesLoading` listing all failed resources.
+    pub fn collect_all_errors(mut self) -> Self {
+        self.collect_all_errors = true;
+        self
+    }
+
+    /// Uses given preloads while loading the resources of the mail.
+    ///
+    /// `Context::use_preload` is called for each preload before the
+    /// resources are loaded and `Context::stop_using_preload` once
+    /// loading completed, failed or the future was dropped. This allows
+    /// e.g. opening an archive the resources are loaded from only once
+    /// per mail.
+    pub fn use_preloads<I>(mut self, preloads: I) -> Self
+        where I: IntoIterator<Item=IRI>
+    {
+        self.preloads.extend(preloads);
+        self
+    }
+
+    /// Creates the `MailFuture` using the set options.
+    pub fn build(self) -> MailFuture<C> {
+        let MailFutureBuilder { mail, ctx, preloads, collect_all_errors } = self;
+        MailFuture {
+            inner: InnerMailFuture::New { mail, ctx, preloads, collect_all_errors }
+        }
+    }
+}
+
+/// Keeps preloads in use until it is dropped.
+struct PreloadScope<C: Context> {
+    ctx: C,
+    preloads: Vec<IRI>
+}
+
+impl<C> PreloadScope<C>
+    where C: Context
+{
+    fn new(ctx: C, preloads: Vec<IRI>) -> Self {
+        for preload in preloads.iter() {
+            ctx.use_preload(preload);
+        }
+        PreloadScope { ctx, preloads }
+    }
+}
+
+impl<C> Drop for PreloadScope<C>
+    where C: Context
+{
+    fn drop(&mut self) {
+        for preload in self.preloads.iter() {
+            self.ctx.stop_using_preload(preload);
+        }
     }
 }
 
@@ -458,48 +674,50 @@ impl<T> Future for MailFuture<T>
         loop {
             let state = mem::replace(&mut self.inner, InnerMailFuture::Poison);
             match state {
-                New { mail, ctx } => {
+                New { mail, ctx, preloads, collect_all_errors } => {
                     mail.generally_validate_mail()?;
                     top_level_validation(&mail)?;
 
-                    let mut futures = Vec::new();
-                    mail.visit_mail_bodies(&mut |resource: &Resource| {
-                        let fut = match resource {
+                    let preloads = PreloadScope::new(ctx.clone(), preloads);
+                    let mut loads = Vec::new();
+                    mail.visit_mail_bodies_with_position(&mut Vec::new(), &mut |position, resource| {
+                        let (source_iri, state) = match resource {
                             &Resource::Source(ref source) => {
-                                Either::A(ctx.load_resource(source))
+                                (Some(source.iri.clone()), LoadState::Queued(resource.clone()))
                             },
-                            &Resource::Data(ref data) => {
-                                Either::A(ctx.transfer_encode_resource(data))
+                            &Resource::Data(_) => {
+                                (None, LoadState::Queued(resource.clone()))
                             },
                             &Resource::EncData(ref enc_data) => {
-                                Either::B(future::ok(enc_data.clone()))
+                                (None, LoadState::Loaded(enc_data.clone()))
                             }
                         };
 
-                        futures.push(fut);
+                        loads.push(ResourceLoad { position: position.to_vec(), source_iri, state });
                     });
 
+                    let pending = LoadResources::new(ctx.clone(), loads, collect_all_errors);
                     mem::replace(
                         &mut self.inner,
                         InnerMailFuture::Loading {
-                            mail, ctx,
-                            pending: future::join_all(futures)
+                            mail, ctx, pending,
+                            _preloads: preloads
                         }
                     );
                 },
-                Loading { mut mail, mut pending, ctx } => {
+                Loading { mut mail, mut pending, ctx, _preloads } => {
                     match pending.poll() {
-                        Err(err) => return Err(err.into()),
+                        Err(err) => return Err(err),
                         Ok(Async::NotReady) => {
                             mem::replace(
                                 &mut self.inner,
-                                InnerMailFuture::Loading { mail, pending, ctx }
+                                InnerMailFuture::Loading { mail, pending, ctx, _preloads }
                             );
                             return Ok(Async::NotReady);
                         },
                         Ok(Async::Ready(encoded_bodies)) => {
-                            auto_gen_headers(&mut mail, encoded_bodies, &ctx);
-                            return Ok(Async::Ready(EncodableMail(mail)));
+                            let generated = auto_gen_headers(&mut mail, encoded_bodies, &ctx);
+                            return Ok(Async::Ready(EncodableMail(mail, generated)));
                         }
                     }
                 },
@@ -509,9 +727,247 @@ impl<T> Future for MailFuture<T>
     }
 }
 
+enum LoadState {
+    /// Waiting for a free slot (see `LoadingLimits::max_in_flight`).
+    Queued(Resource),
+    /// Loading with an optional deadline.
+    Loading(SendBoxFuture<EncData, ResourceLoadingError>, Option<Deadline>),
+    Loaded(EncData),
+    Failed(ResourceLoadingError)
+}
+
+impl LoadState {
+    fn is_done(&self) -> bool {
+        match *self {
+            LoadState::Loaded(_) | LoadState::Failed(_) => true,
+            LoadState::Queued(_) | LoadState::Loading(..) => false
+        }
+    }
+}
+
+/// The loading of a single resource of a mail.
+struct ResourceLoad {
+    position: Vec<usize>,
+    source_iri: Option<IRI>,
+    state: LoadState
+}
+
+impl ResourceLoad {
+
+    /// Sets the state to loaded/failed, fails if it failed and not all errors are collected.
+    fn finish(
+        &mut self,
+        result: Result<EncData, ResourceLoadingError>,
+        collect_all_errors: bool
+    ) -> Result<(), MailError> {
+        match result {
+            Ok(enc_data) => self.state = LoadState::Loaded(enc_data),
+            Err(err) => {
+                if !collect_all_errors {
+                    return Err(err.into());
+                }
+                self.state = LoadState::Failed(err);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn timeout_error(source_iri: &Option<IRI>) -> ResourceLoadingError {
+    ResourceLoadingError::from(ResourceLoadingErrorKind::Timeout)
+        .with_source_iri_or_else(|| source_iri.clone())
+}
+
+/// Loads the resources of a mail.
+///
+/// Like `future::join_all` except that it respects the contexts
+/// `LoadingLimits` and optionally runs all loads to completion
+/// collecting all errors (with the position of the resource).
+struct LoadResources<C: Context> {
+    ctx: C,
+    loads: Vec<ResourceLoad>,
+    limits: LoadingLimits,
+    deadline: Option<Deadline>,
+    collect_all_errors: bool
+}
+
+impl<C> LoadResources<C>
+    where C: Context
+{
+    fn new(ctx: C, loads: Vec<ResourceLoad>, collect_all_errors: bool) -> Self {
+        let limits = ctx.loading_limits();
+        let deadline = deadline_after(limits.overall_timeout);
+        LoadResources { ctx, loads, limits, deadline, collect_all_errors }
+    }
+
+    /// Polls all started loads, failing them if their deadline expired.
+    fn poll_loading(&mut self, expired: bool) -> Result<(), MailError> {
+        for load in self.loads.iter_mut() {
+            let result =
+                match load.state {
+                    LoadState::Loading(ref mut fut, ref mut deadline) => {
+                        match fut.poll() {
+                            Ok(Async::Ready(enc_data)) => Ok(enc_data),
+                            Err(err) => Err(err),
+                            Ok(Async::NotReady) => {
+                                let timed_out =
+                                    if expired {
+                                        Ok(true)
+                                    } else {
+                                        deadline.as_mut().map(has_expired).unwrap_or(Ok(false))
+                                    };
+
+                                match timed_out {
+                                    Ok(false) => continue,
+                                    Ok(true) => Err(timeout_error(&load.source_iri)),
+                                    Err(err) => {
+                                        let source_iri = load.source_iri.clone();
+                                        Err(err.with_source_iri_or_else(move || source_iri))
+                                    }
+                                }
+                            }
+                        }
+                    },
+                    _ => continue
+                };
+
+            load.finish(result, self.collect_all_errors)?;
+        }
+        Ok(())
+    }
+
+    /// Starts queued loads as long as `max_in_flight` isn't reached.
+    ///
+    /// Returns true if any load was started.
+    fn start_queued(&mut self, expired: bool) -> Result<bool, MailError> {
+        let max_in_flight = self.limits.max_in_flight.map(|max| max.max(1));
+        let mut in_flight = self.loads.iter()
+            .filter(|load| match load.state { LoadState::Loading(..) => true, _ => false })
+            .count();
+
+        let mut started = false;
+        for load in self.loads.iter_mut() {
+            let slots_full = max_in_flight.map(|max| in_flight >= max).unwrap_or(false);
+            let fut: SendBoxFuture<EncData, ResourceLoadingError> =
+                match load.state {
+                    LoadState::Queued(_) if expired => {
+                        let err = timeout_error(&load.source_iri);
+                        load.finish(Err(err), self.collect_all_errors)?;
+                        continue;
+                    },
+                    LoadState::Queued(_) if slots_full => break,
+                    LoadState::Queued(Resource::Source(ref source)) => {
+                        self.ctx.load_resource(source)
+                    },
+                    LoadState::Queued(Resource::Data(ref data)) => {
+                        self.ctx.transfer_encode_resource(data)
+                    },
+                    LoadState::Queued(Resource::EncData(ref enc_data)) => {
+                        Box::new(future::ok(enc_data.clone()))
+                    },
+                    _ => continue
+                };
+
+            let deadline = deadline_after(self.limits.resource_timeout);
+            load.state = LoadState::Loading(fut, deadline);
+            in_flight += 1;
+            started = true;
+        }
+        Ok(started)
+    }
+}
+
+#[cfg(feature="loading_timeouts")]
+type Deadline = Delay;
+
+/// Timeouts are ignored without the `loading_timeouts` feature, so no deadline is ever created.
+#[cfg(not(feature="loading_timeouts"))]
+enum Deadline {}
+
+#[cfg(feature="loading_timeouts")]
+fn deadline_after(timeout: Option<Duration>) -> Option<Deadline> {
+    timeout.map(Delay::new)
+}
+
+#[cfg(not(feature="loading_timeouts"))]
+fn deadline_after(timeout: Option<Duration>) -> Option<Deadline> {
+    if timeout.is_some() {
+        warn!("ignoring loading timeout, it requires the `loading_timeouts` feature");
+    }
+    None
+}
+
+/// Polls the deadline returning true if it expired.
+///
+/// If the timer failed (e.g. because the timer thread is gone) a
+/// `LoadingFailed` error with the timer error as cause is returned.
+#[cfg(feature="loading_timeouts")]
+fn has_expired(deadline: &mut Deadline) -> Result<bool, ResourceLoadingError> {
+    match deadline.poll() {
+        Ok(Async::NotReady) => Ok(false),
+        Ok(Async::Ready(())) => Ok(true),
+        // converting the io error uses `LoadingFailed` with it as cause
+        Err(err) => Err(ResourceLoadingError::from(err))
+    }
+}
+
+#[cfg(not(feature="loading_timeouts"))]
+fn has_expired(deadline: &mut Deadline) -> Result<bool, ResourceLoadingError> {
+    match *deadline {}
+}
+
+impl<C> Future for LoadResources<C>
+    where C: Context
+{
+    type Item = Vec<EncData>;
+    type Error = MailError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let expired = self.deadline.as_mut().map(has_expired).unwrap_or(Ok(false))?;
+
+        // loads started (and completed) while polling can free up slots
+        loop {
+            self.poll_loading(expired)?;
+            if !self.start_queued(expired)? {
+                break;
+            }
+        }
+
+        if !self.loads.iter().all(|load| load.state.is_done()) {
+            return Ok(Async::NotReady);
+        }
+
+        let mut loaded = Vec::with_capacity(self.loads.len());
+        let mut failures = Vec::new();
+        for load in mem::replace(&mut self.loads, Vec::new()) {
+            let ResourceLoad { position, state, .. } = load;
+            match state {
+                LoadState::Loaded(enc_data) => loaded.push(enc_data),
+                LoadState::Failed(error) => failures.push(ResourceLoadingFailure { position, error }),
+                LoadState::Queued(_) | LoadState::Loading(..) => {
+                    unreachable!("[BUG] resource still loading")
+                }
+            }
+        }
+
+        if failures.is_empty() {
+            Ok(Async::Ready(loaded))
+        } else {
+            Err(MailError::ResourcesLoading(ResourceLoadingErrors::new(failures)))
+        }
+    }
+}
+
 /// a mail with all contained futures resolved, so that it can be encoded
 #[derive(Clone)]
-pub struct EncodableMail(Mail);
+pub struct EncodableMail(Mail, GeneratedHeaders);
+
+/// Keeps track of which top level headers had been auto-generated.
+#[derive(Debug, Clone, Copy, Default)]
+struct GeneratedHeaders {
+    date: bool,
+    message_id: bool
+}
 
 impl EncodableMail {
 
@@ -521,6 +977,11 @@ impl EncodableMail {
     /// a fully encoded mail including all attachments, embedded
     /// images alternate bodies etc.
     ///
+    /// Bodies which use a transfer encoding not supported by the
+    /// buffers `MailType` (e.g. `8bit` for `MailType::Ascii`) are
+    /// re-encoded "on the fly" with quoted-printable or base64 and
+    /// the `Content-Transfer-Encoding` header is adapted accordingly.
+    ///
     /// # Error
     ///
     /// This can fail for a large number of reasons, e.g. some
@@ -536,6 +997,100 @@ impl EncodableMail {
         self.encode(&mut buffer)?;
         Ok(buffer.into())
     }
+
+    /// Encodes the mail and writes it to given writer.
+    ///
+    /// In difference to `encode_into_bytes` the mail is never fully
+    /// encoded into memory. Headers are encoded (per mail part) into a
+    /// small buffer which is then written to the writer, while the
+    /// already transfer encoded bodies (e.g. large attachments) are
+    /// written to the writer directly.
+    ///
+    /// As this will do many small writes it is recommended to use a
+    /// buffered writer (e.g. `std::io::BufWriter`).
+    ///
+    /// # Error
+    ///
+    /// Fails for the same reasons as `encode` or if writing to the
+    /// writer fails (`MailError::Io`). If it fails the writer might
+    /// already contain a part of the mail.
+    pub fn encode_to_writer(&self, writer: &mut impl Write, mail_type: MailType)
+        -> Result<(), MailError>
+    {
+        {
+            let mut sink = WriterSink::new(writer, mail_type);
+            ::encode::encode_mail(self, true, &mut sink)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Turns the mail into a `Stream` of chunks of the encoded mail.
+    ///
+    /// This can be used to e.g. send the mail using the SMTP `CHUNKING`
+    /// extension (`BDAT`) or to upload it over HTTP without having the
+    /// whole encoded mail in memory. All chunks except the last one have
+    /// exactly `chunk_size` bytes.
+    ///
+    /// The mail is encoded part by part while the stream is polled, as
+    /// such encoding errors are returned through the stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is 0.
+    pub fn into_chunk_stream(self, mail_type: MailType, chunk_size: usize)
+        -> MailChunkStream
+    {
+        MailChunkStream::new(self, mail_type, chunk_size)
+    }
+
+    /// Turns the encodable mail back into a mail which can be edited.
+    ///
+    /// In difference to `Into<Mail>` this "un-finalizes" the mail so
+    /// that it can be edited and then be turned into an encodable
+    /// mail again (e.g. to resend it). For this it will:
+    ///
+    /// 1. Transfer decode all resources turning them back into `Resource::Data`.
+    /// 2. Remove the generated `boundary` parameter from all multipart media types.
+    /// 3. Remove the auto-generated `Date` and `Message-Id` headers, if
+    ///    `remove_generated_headers` is `true`. Headers which were set
+    ///    before the mail was turned into an encodable mail are always kept.
+    ///
+    /// # Error
+    ///
+    /// Fails if transfer decoding any of the resources fails.
+    pub fn into_editable_mail(self, remove_generated_headers: bool) -> Result<Mail, MailError> {
+        let EncodableMail(mut mail, generated) = self;
+
+        if remove_generated_headers {
+            let headers = mail.headers_mut();
+            if generated.date {
+                headers.remove(Date);
+            }
+            if generated.message_id {
+                headers.remove(MessageId);
+            }
+        }
+
+        let mut result = Ok(());
+        mail.visit_mail_bodies_mut(&mut |resource: &mut Resource| {
+            if result.is_err() {
+                return;
+            }
+            let decoded = match resource {
+                &mut Resource::EncData(ref enc_data) => enc_data.transfer_decode(),
+                _ => return
+            };
+            match decoded {
+                Ok(data) => *resource = Resource::Data(data),
+                Err(err) => result = Err(err)
+            }
+        });
+        result?;
+
+        remove_boundaries(&mut mail);
+        Ok(mail)
+    }
 }
 
 fn top_level_validation(mail: &Mail) -> Result<(), HeaderValidationError> {
@@ -553,15 +1108,18 @@ fn auto_gen_headers<C: Context>(
     mail: &mut Mail,
     encoded_resources: Vec<EncData>,
     ctx: &C
-) {
+) -> GeneratedHeaders {
+    let mut generated = GeneratedHeaders::default();
     {
         let headers = mail.headers_mut();
         if !headers.contains(Date) {
             headers.insert(Date::body(DateTime::now()));
+            generated.date = true;
         }
 
         if !headers.contains(MessageId) {
             headers.insert(MessageId::body(ctx.generate_message_id()));
+            generated.message_id = true;
         }
     }
 
@@ -574,6 +1132,7 @@ fn auto_gen_headers<C: Context>(
 
     let mut boundary_count = 0;
     recursive_auto_gen_headers(mail, &mut boundary_count, ctx);
+    generated
 }
 
 /// returns the `EncData` from a resource
@@ -609,13 +1168,27 @@ fn recursive_auto_gen_headers<C: Context>(mail: &mut Mail, boundary_count: &mut
             *boundary_count += 1;
             content_type.set_param(BOUNDARY, boundary);
 
-            for sub_mail in bodies {
+            for sub_mail in bodies.iter_mut() {
                 recursive_auto_gen_headers(sub_mail, boundary_count, ctx);
             }
         }
     }
 }
 
+/// removes the boundary parameter from all multipart content types
+fn remove_boundaries(mail: &mut Mail) {
+    let &mut Mail { ref mut headers, ref mut body } = mail;
+    if let &mut MailBody::MultipleBodies { ref mut bodies, .. } = body {
+        if let Some(Ok(content_type)) = headers.get_single_mut(ContentType) {
+            content_type.remove_param(BOUNDARY);
+        }
+
+        for sub_mail in bodies.iter_mut() {
+            remove_boundaries(sub_mail);
+        }
+    }
+}
+
 pub(crate) fn validate_multipart_headermap(headers: &HeaderMap)
     -> Result<(), MailError>
 {
@@ -659,7 +1232,7 @@ impl Deref for EncodableMail {
 
 impl Into<Mail> for EncodableMail {
     fn into(self) -> Mail {
-        let EncodableMail(mail) = self;
+        let EncodableMail(mail, _) = self;
         mail
     }
 }
@@ -703,11 +1276,11 @@ mod test {
             let mail = Mail {
                 headers: HeaderMap::new(),
                 body: MailBody::MultipleBodies {
-                    bodies: vec! [
+                    bodies: Vec1::try_from_vec(vec! [
                         Mail {
                             headers: HeaderMap::new(),
                             body: MailBody::MultipleBodies {
-                                bodies: vec! [
+                                bodies: Vec1::try_from_vec(vec! [
                                     Mail {
                                         headers: HeaderMap::new(),
                                         body: MailBody::SingleBody {
@@ -720,8 +1293,9 @@ mod test {
                                             body: Resource::plain_text("r2", &ctx)
                                         }
                                     }
-                                ],
-                                hidden_text: Default::default()
+                                ]).unwrap(),
+                                hidden_text: Default::default(),
+                                epilogue: Default::default()
                             }
                         },
                         Mail {
@@ -731,8 +1305,9 @@ mod test {
                             }
                         }
 
-                    ],
-                    hidden_text: Default::default()
+                    ]).unwrap(),
+                    hidden_text: Default::default(),
+                    epilogue: Default::default()
                 }
             };
 
@@ -773,16 +1348,345 @@ mod test {
             assert!(mail.headers().contains(Comments));
         });
 
+        #[test]
+        fn unload_resources_unloads_resources_with_source() {
+            let ctx = test_context();
+            let source = Source {
+                iri: IRI::new("mem:r1").unwrap(),
+                use_media_type: Default::default(),
+                use_file_name: None,
+                use_transfer_encoding: None
+            };
+            let loaded = Data::new(&b"r1"[..], Metadata {
+                file_meta: Default::default(),
+                media_type: MediaType::parse("text/plain").unwrap(),
+                content_id: ctx.generate_content_id(),
+                source: Some(source),
+                transfer_encoding_hint: Default::default()
+            });
+            let mut mail = Mail::new_multipart_mail(
+                MediaType::parse("multipart/mixed").unwrap(),
+                Vec1::try_from_vec(vec![
+                    Mail::new_singlepart_mail(Resource::Data(loaded)),
+                    Mail::plain_text("r2", &ctx)
+                ]).unwrap()
+            );
+
+            assert_eq!(mail.unload_resources(), 1);
+
+            let mut resources = Vec::new();
+            mail.visit_mail_bodies(&mut |resource: &Resource| resources.push(resource.is_source()));
+            assert_eq!(resources, vec![true, false]);
+        }
+
+    }
+
+    mod MailFuture {
+        #![allow(non_snake_case)]
+        use std::sync::Mutex;
+        use futures::{future, sync::oneshot};
+        use futures_cpupool::CpuPool;
+        use headers::headers::_From;
+        use context::{CompositeContext, ResourceLoaderComponent};
+        use default_impl::{test_context, simple_cpu_pool, HashedIdGen};
+        use super::super::*;
+
+        /// Resource loader recording calls to the preload functions.
+        #[derive(Debug, Default)]
+        struct PreloadRecorder {
+            events: Mutex<Vec<String>>
+        }
+
+        impl ResourceLoaderComponent for PreloadRecorder {
+            fn load_resource(&self, source: &Source, _: &impl Context)
+                -> SendBoxFuture<EncData, ResourceLoadingError>
+            {
+                panic!("unexpected load of {:?}", source)
+            }
+
+            fn use_preload(&self, preload: &IRI) {
+                self.events.lock().unwrap().push(format!("use {}", preload.as_str()));
+            }
+
+            fn stop_using_preload(&self, preload: &IRI) {
+                self.events.lock().unwrap().push(format!("stop {}", preload.as_str()));
+            }
+        }
+
+        type RecordingContext = CompositeContext<PreloadRecorder, CpuPool, HashedIdGen>;
+
+        fn recording_context() -> RecordingContext {
+            let id_gen = test_context().id_gen().clone();
+            CompositeContext::new(PreloadRecorder::default(), simple_cpu_pool(), id_gen)
+        }
+
+        fn events(ctx: &RecordingContext) -> Vec<String> {
+            ctx.resource_loader().events.lock().unwrap().clone()
+        }
+
+        fn preloads() -> Vec<IRI> {
+            vec![IRI::new("archive:a.zip").unwrap(), IRI::new("archive:b.tar").unwrap()]
+        }
+
+        test!(uses_preloads_while_loading, {
+            let ctx = recording_context();
+            let mut mail = Mail::plain_text("r0", &ctx);
+            mail.insert_headers(headers! { _From: ["random@this.is.no.mail"] }?);
+
+            let _enc_mail = mail.into_encodable_mail_builder(ctx.clone())
+                .use_preloads(preloads())
+                .build()
+                .wait()?;
+
+            assert_eq!(events(&ctx), vec![
+                "use archive:a.zip", "use archive:b.tar",
+                "stop archive:a.zip", "stop archive:b.tar"
+            ]);
+        });
+
+        #[test]
+        fn stops_using_preloads_if_dropped() {
+            let ctx = recording_context();
+            let mut mail = Mail::plain_text("r0", &ctx);
+            mail.insert_headers(headers! { _From: ["random@this.is.no.mail"] }.unwrap());
+
+            let mut fut = mail.into_encodable_mail_builder(ctx.clone())
+                .use_preloads(preloads())
+                .build();
+            // poll once (inside of a task) to start loading the resources
+            future::lazy(|| {
+                let _ = fut.poll();
+                Ok::<(), ()>(())
+            }).wait().unwrap();
+            drop(fut);
+
+            let events = events(&ctx);
+            assert_eq!(events.len(), 4);
+            assert_eq!(&events[2..], &["stop archive:a.zip", "stop archive:b.tar"]);
+        }
+
+        /// Mail with the resources `mem:missing1`, `r0` and `mem:missing2`
+        /// at the positions `[0]`, `[1, 0]` and `[1, 1]`.
+        fn mail_with_missing_resources(ctx: &impl Context) -> Result<Mail, MailError> {
+            let missing = |iri: &str| Mail::new_singlepart_mail(Resource::Source(Source {
+                iri: IRI::new(iri).unwrap(),
+                use_media_type: Default::default(),
+                use_file_name: None,
+                use_transfer_encoding: None
+            }));
+            let mixed = || MediaType::parse("multipart/mixed").unwrap();
+
+            let inner = Mail::new_multipart_mail(mixed(), Vec1::try_from_vec(vec![
+                Mail::plain_text("r0", ctx),
+                missing("mem:missing2")
+            ]).unwrap());
+
+            let mut mail = Mail::new_multipart_mail(mixed(), Vec1::try_from_vec(vec![
+                missing("mem:missing1"),
+                inner
+            ]).unwrap());
+            mail.insert_headers(headers! { _From: ["random@this.is.no.mail"] }?);
+            Ok(mail)
+        }
+
+        test!(fails_with_first_error_by_default, {
+            let ctx = test_context();
+            let mail = mail_with_missing_resources(&ctx)?;
+
+            match assert_err!(mail.into_encodable_mail(ctx).wait()) {
+                MailError::ResourceLoading(_) => {},
+                other => panic!("unexpected error: {:?}", other)
+            }
+        });
+
+        test!(collects_all_errors_if_requested, {
+            let ctx = test_context();
+            let mail = mail_with_missing_resources(&ctx)?;
+
+            let err = assert_err!(mail.into_encodable_mail_builder(ctx)
+                .collect_all_errors()
+                .build()
+                .wait());
+            let errors = match err {
+                MailError::ResourcesLoading(errors) => errors,
+                other => panic!("unexpected error: {:?}", other)
+            };
+
+            let failures = errors.failures();
+            assert_eq!(failures.len(), 2);
+            assert_eq!(failures[0].position, vec![0]);
+            assert_eq!(failures[0].error.source_iri().unwrap().as_str(), "mem:missing1");
+            assert_eq!(failures[1].position, vec![1, 1]);
+            assert_eq!(failures[1].error.source_iri().unwrap().as_str(), "mem:missing2");
+
+            assert_eq!(failures[0].mime_path(), "1");
+            assert_eq!(failures[1].mime_path(), "2.2");
+            let display = errors.to_string();
+            assert!(display.contains(" 2.2 (mem:missing2): "), "{}", display);
+        });
+
+        /// Resource loader whose loads only complete once released by the test.
+        #[derive(Debug, Default)]
+        struct ManualLoader {
+            pending: Mutex<Vec<(IRI, oneshot::Sender<EncData>)>>
+        }
+
+        impl ManualLoader {
+            fn started(&self) -> Vec<String> {
+                self.pending.lock().unwrap().iter()
+                    .map(|&(ref iri, _)| iri.as_str().to_owned())
+                    .collect()
+            }
+
+            fn release(&self, iri: &str, ctx: &impl Context) {
+                let mut pending = self.pending.lock().unwrap();
+                let idx = pending.iter().position(|&(ref other, _)| other.as_str() == iri).unwrap();
+                let (_, sender) = pending.remove(idx);
+                let data = Data::plain_text(iri, ctx.generate_content_id());
+                sender.send(data.transfer_encode(Default::default())).unwrap();
+            }
+        }
+
+        impl ResourceLoaderComponent for ManualLoader {
+            fn load_resource(&self, source: &Source, _: &impl Context)
+                -> SendBoxFuture<EncData, ResourceLoadingError>
+            {
+                let (sender, receiver) = oneshot::channel();
+                self.pending.lock().unwrap().push((source.iri.clone(), sender));
+                Box::new(receiver.map_err(|_| ResourceLoadingError::from(ResourceLoadingErrorKind::LoadingFailed)))
+            }
+        }
+
+        type ManualContext = CompositeContext<ManualLoader, CpuPool, HashedIdGen>;
+
+        fn manual_context(limits: LoadingLimits) -> ManualContext {
+            let id_gen = test_context().id_gen().clone();
+            CompositeContext::new(ManualLoader::default(), simple_cpu_pool(), id_gen)
+                .with_loading_limits(limits)
+        }
+
+        /// Mail with the resources `mem:r0`, `mem:r1` and `mem:r2`.
+        fn mail_with_resources(ctx: &impl Context) -> Result<Mail, MailError> {
+            let bodies = (0..3).map(|idx| Mail::new_singlepart_mail(Resource::Source(Source {
+                iri: IRI::new(format!("mem:r{}", idx)).unwrap(),
+                use_media_type: Default::default(),
+                use_file_name: None,
+                use_transfer_encoding: None
+            }))).collect();
+
+            let mut mail = Mail::new_multipart_mail(
+                MediaType::parse("multipart/mixed").unwrap(),
+                Vec1::try_from_vec(bodies).unwrap()
+            );
+            mail.insert_headers(headers! { _From: ["random@this.is.no.mail"] }?);
+            Ok(mail)
+        }
+
+        fn poll_in_task<F: Future>(fut: &mut F) -> Result<Async<F::Item>, F::Error> {
+            future::lazy(|| Ok::<_, ()>(fut.poll())).wait().unwrap()
+        }
+
+        test!(limits_the_number_of_concurrent_loads, {
+            let ctx = manual_context(LoadingLimits {
+                max_in_flight: Some(2),
+                ..Default::default()
+            });
+            let mut fut = mail_with_resources(&ctx)?.into_encodable_mail(ctx.clone());
+
+            assert!(poll_in_task(&mut fut)?.is_not_ready());
+            assert_eq!(ctx.resource_loader().started(), vec!["mem:r0", "mem:r1"]);
+
+            ctx.resource_loader().release("mem:r1", &ctx);
+            assert!(poll_in_task(&mut fut)?.is_not_ready());
+            assert_eq!(ctx.resource_loader().started(), vec!["mem:r0", "mem:r2"]);
+
+            ctx.resource_loader().release("mem:r0", &ctx);
+            ctx.resource_loader().release("mem:r2", &ctx);
+            assert!(poll_in_task(&mut fut)?.is_ready());
+        });
+
+        #[cfg(feature="loading_timeouts")]
+        #[test]
+        fn fails_with_timeout_if_a_resource_takes_too_long() {
+            let ctx = manual_context(LoadingLimits {
+                resource_timeout: Some(Duration::from_millis(10)),
+                ..Default::default()
+            });
+            let mail = mail_with_resources(&ctx).unwrap();
+
+            match assert_err!(mail.into_encodable_mail(ctx).wait()) {
+                MailError::ResourceLoading(err) => {
+                    assert_eq!(err.kind(), ResourceLoadingErrorKind::Timeout);
+                    assert!(err.source_iri().is_some());
+                },
+                other => panic!("unexpected error: {:?}", other)
+            }
+        }
+
+        #[cfg(feature="loading_timeouts")]
+        #[test]
+        fn overall_timeout_fails_all_pending_resources() {
+            let ctx = manual_context(LoadingLimits {
+                max_in_flight: Some(1),
+                overall_timeout: Some(Duration::from_millis(10)),
+                ..Default::default()
+            });
+            let mail = mail_with_resources(&ctx).unwrap();
+
+            let err = assert_err!(mail.into_encodable_mail_builder(ctx.clone())
+                .collect_all_errors()
+                .build()
+                .wait());
+            let errors = match err {
+                MailError::ResourcesLoading(errors) => errors,
+                other => panic!("unexpected error: {:?}", other)
+            };
+
+            let failures = errors.failures();
+            assert_eq!(failures.len(), 3);
+            for (idx, failure) in failures.iter().enumerate() {
+                assert_eq!(failure.error.kind(), ResourceLoadingErrorKind::Timeout);
+                assert_eq!(failure.error.source_iri().unwrap().as_str(), format!("mem:r{}", idx));
+            }
+            // the queued resources are never started
+            assert_eq!(ctx.resource_loader().started(), vec!["mem:r0"]);
+        }
+
+        #[cfg(feature="loading_timeouts")]
+        #[test]
+        fn timer_errors_fail_with_loading_failed() {
+            use std::time::Instant;
+            use futures_timer::{Timer, Delay};
+
+            let handle = {
+                let timer = Timer::new();
+                timer.handle()
+            };
+            let mut deadline = Delay::new_handle(Instant::now() + Duration::from_secs(60), handle);
+
+            let err = assert_err!(has_expired(&mut deadline));
+            assert_eq!(err.kind(), ResourceLoadingErrorKind::LoadingFailed);
+        }
+
+        test!(collect_all_errors_succeeds_if_all_resources_load, {
+            let ctx = test_context();
+            let mut mail = Mail::plain_text("r0", &ctx);
+            mail.insert_headers(headers! { _From: ["random@this.is.no.mail"] }?);
+
+            mail.into_encodable_mail_builder(ctx).collect_all_errors().build().wait()?;
+        });
     }
 
     mod EncodableMail {
         #![allow(non_snake_case)]
         use chrono::{Utc, TimeZone};
+        use futures::Stream;
         use headers::{
             headers::{
                 _From, ContentType, ContentTransferEncoding,
                 Date, Subject
-            }
+            },
+            header_components::TransferEncoding
         };
         use default_impl::test_context;
         use super::super::*;
@@ -829,13 +1733,14 @@ mod test {
                     ContentType: "multipart/mixed"
                 }.unwrap(),
                 body: MailBody::MultipleBodies {
-                    bodies: vec![
+                    bodies: Vec1::new(
                         Mail {
                             headers: HeaderMap::new(),
                             body: MailBody::SingleBody { body: resource }
                         }
-                    ],
-                    hidden_text: Default::default()
+                    ),
+                    hidden_text: Default::default(),
+                    epilogue: Default::default()
                 }
             };
 
@@ -901,6 +1806,170 @@ mod test {
             assert_eq!(&**used_date.body(), &provided_date);
         });
 
+        test!(encodes_preamble_and_epilogue, {
+            let ctx = test_context();
+            let mut mail = Mail::plain_text("r9", &ctx)
+                .wrap_with_mixed(vec![])
+                .with_preamble(SoftAsciiString::from_unchecked("This is a multi-part message in MIME format."))?
+                .with_epilogue(SoftAsciiString::from_unchecked("the end"))?;
+            mail.insert_headers(headers! {
+                _From: ["random@this.is.no.mail"]
+            }?);
+
+            let enc_mail = assert_ok!(mail.into_encodable_mail(ctx).wait());
+            let encoded = enc_mail.encode_into_bytes(MailType::Ascii)?;
+            let encoded = String::from_utf8(encoded).unwrap();
+
+            assert!(encoded.contains("\r\n\r\nThis is a multi-part message in MIME format.\r\n--=_^0."));
+            assert!(encoded.ends_with("--\r\nthe end\r\n"));
+        });
+
+        test!(encode_to_writer_writes_the_encoded_mail, {
+            let ctx = test_context();
+            let mut mail = Mail::plain_text("r9", &ctx)
+                .wrap_with_mixed(vec![Mail::plain_text("r8", &ctx)]);
+            mail.insert_headers(headers! {
+                _From: ["random@this.is.no.mail"],
+                Subject: "hoho"
+            }?);
+
+            let enc_mail = assert_ok!(mail.into_encodable_mail(ctx).wait());
+            let mut out = Vec::new();
+            enc_mail.encode_to_writer(&mut out, MailType::Ascii)?;
+
+            assert_eq!(out, enc_mail.encode_into_bytes(MailType::Ascii)?);
+        });
+
+        test!(into_chunk_stream_streams_the_encoded_mail, {
+            let ctx = test_context();
+            let mut mail = Mail::plain_text("r9", &ctx)
+                .wrap_with_mixed(vec![Mail::plain_text("r8", &ctx)]);
+            mail.insert_headers(headers! {
+                _From: ["random@this.is.no.mail"],
+                Subject: "hoho"
+            }?);
+
+            let enc_mail = assert_ok!(mail.into_encodable_mail(ctx).wait());
+            let expected = enc_mail.encode_into_bytes(MailType::Ascii)?;
+
+            let chunks = enc_mail.into_chunk_stream(MailType::Ascii, 7)
+                .collect()
+                .wait()?;
+
+            let (last, others) = chunks.split_last().unwrap();
+            assert!(others.iter().all(|chunk| chunk.len() == 7));
+            assert!(last.len() > 0 && last.len() <= 7);
+            assert_eq!(chunks.concat(), expected);
+        });
+
+        fn mail_with_8bit_body(ctx: &impl Context) -> Result<Mail, MailError> {
+            let meta = Metadata {
+                file_meta: Default::default(),
+                media_type: MediaType::parse("text/plain; charset=utf-8").unwrap(),
+                content_id: ctx.generate_content_id(),
+                source: None,
+                transfer_encoding_hint: Default::default()
+            };
+            let enc_data = EncData::new("ümlaut".as_bytes(), meta, TransferEncoding::_8Bit);
+            let mut mail = Mail::new_singlepart_mail(Resource::EncData(enc_data));
+            mail.insert_headers(headers! {
+                _From: ["random@this.is.no.mail"]
+            }?);
+            Ok(mail)
+        }
+
+        test!(re_encodes_8bit_bodies_for_ascii_mails, {
+            let ctx = test_context();
+            let mail = mail_with_8bit_body(&ctx)?;
+
+            let enc_mail = assert_ok!(mail.into_encodable_mail(ctx).wait());
+            let encoded = enc_mail.encode_into_bytes(MailType::Ascii)?;
+            let encoded = String::from_utf8(encoded).unwrap();
+
+            // `TransferEncodingHint::Auto` is used for re-encoding and "ümlaut"
+            // has too many non us-ascii bytes for quoted-printable
+            assert!(encoded.to_lowercase().contains("content-transfer-encoding: base64\r\n"));
+            assert!(encoded.contains("\r\n\r\nw7xtbGF1dA=="));
+        });
+
+        test!(keeps_8bit_bodies_for_8bit_mails, {
+            let ctx = test_context();
+            let mail = mail_with_8bit_body(&ctx)?;
+
+            let enc_mail = assert_ok!(mail.into_encodable_mail(ctx).wait());
+            let encoded = enc_mail.encode_into_bytes(MailType::Mime8BitEnabled)?;
+            let encoded = String::from_utf8(encoded).unwrap();
+
+            assert!(encoded.to_lowercase().contains("content-transfer-encoding: 8bit\r\n"));
+            assert!(encoded.contains("\r\n\r\nümlaut"));
+        });
+
+        test!(uses_8bit_for_auto_encoded_text_if_the_mail_type_allows_it, {
+            let ctx = test_context();
+            let mut mail = Mail::plain_text("hy there, ümlaut", &ctx);
+            mail.insert_headers(headers! {
+                _From: ["random@this.is.no.mail"]
+            }?);
+
+            let enc_mail = assert_ok!(mail.into_encodable_mail(ctx).wait());
+
+            let encoded = enc_mail.encode_into_bytes(MailType::Ascii)?;
+            let encoded = String::from_utf8(encoded).unwrap();
+            assert!(encoded.to_lowercase().contains("content-transfer-encoding: quoted-printable\r\n"));
+            assert!(encoded.contains("\r\n\r\nhy there, =C3=BCmlaut"));
+
+            let encoded = enc_mail.encode_into_bytes(MailType::Mime8BitEnabled)?;
+            let encoded = String::from_utf8(encoded).unwrap();
+            assert!(encoded.to_lowercase().contains("content-transfer-encoding: 8bit\r\n"));
+            assert!(encoded.contains("\r\n\r\nhy there, ümlaut"));
+        });
+
+        test!(into_editable_mail_removes_generated_state, {
+            let ctx = test_context();
+            let mut mail = Mail::plain_text("r9", &ctx)
+                .wrap_with_mixed(vec![Mail::plain_text("r8", &ctx)]);
+            mail.insert_headers(headers! {
+                _From: ["random@this.is.no.mail"],
+                Subject: "hoho"
+            }?);
+
+            let enc_mail = assert_ok!(mail.into_encodable_mail(ctx).wait());
+            let mail = enc_mail.into_editable_mail(true)?;
+
+            assert_not!(mail.headers().contains(Date));
+            assert_not!(mail.headers().contains(MessageId));
+            assert!(mail.headers().contains(Subject));
+
+            let content_type = mail.headers().get_single(ContentType).unwrap()?;
+            assert!(content_type.get_param(BOUNDARY).is_none());
+
+            let mut bodies = Vec::new();
+            mail.visit_mail_bodies(&mut |resource: &Resource| {
+                if let &Resource::Data(ref data) = resource {
+                    bodies.push(data.buffer().to_vec());
+                } else {
+                    panic!("unexpected resource: {:?}", resource);
+                }
+            });
+            assert_eq!(bodies, vec![b"r8".to_vec(), b"r9".to_vec()]);
+        });
+
+        test!(into_editable_mail_keeps_provided_headers, {
+            let ctx = test_context();
+            let provided_date = Utc.ymd(1992, 5, 25).and_hms(23, 41, 12);
+            let mut mail = Mail::plain_text("r9", &ctx);
+            mail.insert_headers(headers! {
+                _From: ["random@this.is.no.mail"],
+                Date: provided_date.clone()
+            }?);
+
+            let enc_mail = assert_ok!(mail.into_encodable_mail(ctx).wait());
+            let mail = enc_mail.into_editable_mail(true)?;
+
+            assert!(mail.headers().contains(Date));
+            assert_not!(mail.headers().contains(MessageId));
+        });
+
     }
 
 }
\ No newline at end of file
@@ -1,6 +1,10 @@
 //! Module containing some utilities for MIME usage/creation.
+use std::path::Path;
+
 use rand::{self, Rng};
 
+use headers::header_components::MediaType;
+
 
 
 // The maximal boundary with wich " boundary=\"...\"" fits into 78 chars line length limit
@@ -74,10 +78,237 @@ pub fn create_structured_random_boundary(count: usize) -> String {
     out
 }
 
+/// Container formats which are used by multiple media types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Container {
+    Zip,
+    Ole,
+    IsoBmff
+}
+
+/// Magic byte signatures: (offset, signature, media type or container).
+static MAGIC_SIGNATURES: &[(usize, &[u8], Result<&str, Container>)] = &[
+    (0, b"\x89PNG\r\n\x1a\n", Ok("image/png")),
+    (0, b"\xFF\xD8\xFF", Ok("image/jpeg")),
+    (0, b"GIF87a", Ok("image/gif")),
+    (0, b"GIF89a", Ok("image/gif")),
+    (0, b"II*\x00", Ok("image/tiff")),
+    (0, b"MM\x00*", Ok("image/tiff")),
+    (0, b"%PDF-", Ok("application/pdf")),
+    (0, b"%!PS", Ok("application/postscript")),
+    (0, b"{\\rtf", Ok("application/rtf")),
+    (0, b"\x1F\x8B", Ok("application/gzip")),
+    (0, b"7z\xBC\xAF\x27\x1C", Ok("application/x-7z-compressed")),
+    (0, b"Rar!\x1A\x07", Ok("application/vnd.rar")),
+    (257, b"ustar", Ok("application/x-tar")),
+    (0, b"ID3", Ok("audio/mpeg")),
+    (0, b"OggS", Ok("audio/ogg")),
+    (0, b"fLaC", Ok("audio/flac")),
+    (4, b"ftyp", Err(Container::IsoBmff)),
+    (0, b"PK\x03\x04", Err(Container::Zip)),
+    (0, b"\xD0\xCF\x11\xE0\xA1\xB1\x1A\xE1", Err(Container::Ole)),
+];
+
+/// Major brands of ISO base media files (the 4 bytes after `ftyp`).
+static FTYP_BRANDS: &[(&[u8], &str)] = &[
+    (b"isom", "video/mp4"),
+    (b"iso2", "video/mp4"),
+    (b"mp41", "video/mp4"),
+    (b"mp42", "video/mp4"),
+    (b"avc1", "video/mp4"),
+    (b"heic", "image/heic"),
+    (b"heix", "image/heic"),
+    (b"avif", "image/avif"),
+    (b"M4A ", "audio/mp4"),
+    (b"qt  ", "video/quicktime"),
+];
+
+/// File extensions: (extension, media type, container the format is based on).
+static EXTENSIONS: &[(&str, &str, Option<Container>)] = &[
+    ("txt", "text/plain", None),
+    ("text", "text/plain", None),
+    ("htm", "text/html", None),
+    ("html", "text/html", None),
+    ("css", "text/css", None),
+    ("csv", "text/csv", None),
+    ("ics", "text/calendar", None),
+    ("vcf", "text/vcard", None),
+    ("md", "text/markdown", None),
+    ("xml", "text/xml", None),
+    ("json", "application/json", None),
+    ("js", "application/javascript", None),
+    ("svg", "image/svg+xml", None),
+    ("eml", "message/rfc822", None),
+    ("docx", "application/vnd.openxmlformats-officedocument.wordprocessingml.document", Some(Container::Zip)),
+    ("xlsx", "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet", Some(Container::Zip)),
+    ("pptx", "application/vnd.openxmlformats-officedocument.presentationml.presentation", Some(Container::Zip)),
+    ("odt", "application/vnd.oasis.opendocument.text", Some(Container::Zip)),
+    ("ods", "application/vnd.oasis.opendocument.spreadsheet", Some(Container::Zip)),
+    ("odp", "application/vnd.oasis.opendocument.presentation", Some(Container::Zip)),
+    ("epub", "application/epub+zip", Some(Container::Zip)),
+    ("jar", "application/java-archive", Some(Container::Zip)),
+    ("doc", "application/msword", Some(Container::Ole)),
+    ("xls", "application/vnd.ms-excel", Some(Container::Ole)),
+    ("ppt", "application/vnd.ms-powerpoint", Some(Container::Ole)),
+    ("msg", "application/vnd.ms-outlook", Some(Container::Ole)),
+];
+
+/// Detects the media type of given data.
+///
+/// This is conservative and uses (in order of precedence):
+///
+/// 1. magic byte signatures (the file name extension is used to tell
+///    apart formats based on the same container, e.g. a `.docx` file
+///    is a zip file)
+/// 2. for data which is valid utf-8 and has no unusual control characters
+///    the file name extension (for text formats) or else `text/plain`,
+///    with a `charset` parameter of either `us-ascii` or `utf-8`
+/// 3. `application/octet-stream` as fallback
+///
+/// As it doesn't do any I/O it can be used both for data loaded from
+/// a file and for `Data` created in memory.
+///
+/// # Example
+///
+/// ```
+/// # use mail_core::mime::sniff_media_type;
+/// let media_type = sniff_media_type(b"\x89PNG\r\n\x1a\n...", None);
+/// assert_eq!(media_type.as_str_repr(), "image/png");
+///
+/// let media_type = sniff_media_type(b"<p>hy</p>", Some("index.html"));
+/// assert_eq!(media_type.as_str_repr(), "text/html; charset=us-ascii");
+/// ```
+pub fn sniff_media_type(buffer: &[u8], file_name: Option<&str>) -> MediaType {
+    let extension = file_name
+        .and_then(|name| Path::new(name).extension())
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+
+    let by_extension = extension
+        .and_then(|ext| EXTENSIONS.iter().find(|&&(known, _, _)| known == ext));
+
+    let magic = MAGIC_SIGNATURES.iter()
+        .find(|&&(offset, signature, _)| {
+            buffer.len() >= offset + signature.len()
+                && &buffer[offset..offset + signature.len()] == signature
+        })
+        .map(|&(_, _, result)| result);
+
+    let media_type =
+        match magic {
+            Some(Ok(media_type)) => media_type.to_owned(),
+            Some(Err(container)) => {
+                match by_extension {
+                    Some(&(_, media_type, Some(ext_container))) if ext_container == container =>
+                        media_type.to_owned(),
+                    _ if container == Container::IsoBmff => ftyp_media_type(buffer).to_owned(),
+                    _ if container == Container::Zip => "application/zip".to_owned(),
+                    _ => "application/octet-stream".to_owned()
+                }
+            },
+            None => {
+                match text_charset(buffer) {
+                    Some(charset) => {
+                        let media_type =
+                            match by_extension {
+                                Some(&(_, media_type, None)) => media_type,
+                                _ => "text/plain"
+                            };
+                        if media_type.starts_with("text/") {
+                            format!("{}; charset={}", media_type, charset)
+                        } else {
+                            media_type.to_owned()
+                        }
+                    },
+                    None => "application/octet-stream".to_owned()
+                }
+            }
+        };
+
+    //UNWRAP_SAFE: all media types are created from the static tables above
+    MediaType::parse(&*media_type).unwrap()
+}
+
+/// Returns the media type of a ISO base media file based on it's major brand.
+fn ftyp_media_type(buffer: &[u8]) -> &'static str {
+    let brand = buffer.get(8..12);
+    FTYP_BRANDS.iter()
+        .find(|&&(known, _)| Some(known) == brand)
+        .map(|&(_, media_type)| media_type)
+        .unwrap_or("application/octet-stream")
+}
+
+/// Returns the charset if the buffer looks like text.
+fn text_charset(buffer: &[u8]) -> Option<&'static str> {
+    let has_unusual_controls = buffer.iter().any(|&bch| {
+        bch < 0x20 && bch != b'\t' && bch != b'\n' && bch != b'\r'
+            && bch != 0x0C && bch != 0x1B
+    });
+
+    if has_unusual_controls {
+        None
+    } else if buffer.is_ascii() {
+        Some("us-ascii")
+    } else if ::std::str::from_utf8(buffer).is_ok() {
+        Some("utf-8")
+    } else {
+        None
+    }
+}
+
 
 #[cfg(test)]
 mod test {
 
+    mod sniff_media_type {
+        use super::super::*;
+
+        fn sniff(buffer: &[u8], file_name: Option<&str>) -> String {
+            super::super::sniff_media_type(buffer, file_name).as_str_repr().to_owned()
+        }
+
+        #[test]
+        fn detects_magic_bytes() {
+            assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\x00\x00", None), "image/png");
+            assert_eq!(sniff(b"\xFF\xD8\xFF\xE0\x00\x10JFIF", Some("logo.png")), "image/jpeg");
+            assert_eq!(sniff(b"%PDF-1.4\n", None), "application/pdf");
+        }
+
+        #[test]
+        fn uses_major_brand_for_iso_media_files() {
+            assert_eq!(sniff(b"\x00\x00\x00\x20ftypisom\x00\x00\x02\x00", None), "video/mp4");
+            assert_eq!(sniff(b"\x00\x00\x00\x18ftypheic\x00\x00\x00\x00", None), "image/heic");
+            assert_eq!(sniff(b"\x00\x00\x00\x1cftypavif\x00\x00\x00\x00", None), "image/avif");
+            assert_eq!(sniff(b"\x00\x00\x00\x20ftypM4A \x00\x00\x00\x00", None), "audio/mp4");
+            assert_eq!(sniff(b"\x00\x00\x00\x14ftypqt  \x00\x00\x00\x00", None), "video/quicktime");
+            assert_eq!(sniff(b"\x00\x00\x00\x14ftypcrx \x00\x00\x00\x00", None), "application/octet-stream");
+            assert_eq!(sniff(b"\x00\x00\x00\x14ftyp", None), "application/octet-stream");
+        }
+
+        #[test]
+        fn uses_extension_to_refine_containers() {
+            let zip = b"PK\x03\x04\x14\x00\x06\x00";
+            assert_eq!(sniff(zip, None), "application/zip");
+            assert_eq!(sniff(zip, Some("Report.DOCX")),
+                "application/vnd.openxmlformats-officedocument.wordprocessingml.document");
+            assert_eq!(sniff(zip, Some("report.doc")), "application/zip");
+        }
+
+        #[test]
+        fn detects_text() {
+            assert_eq!(sniff(b"hy there\n", None), "text/plain; charset=us-ascii");
+            assert_eq!(sniff("hy ümlaut".as_bytes(), None), "text/plain; charset=utf-8");
+            assert_eq!(sniff(b"BEGIN:VCALENDAR\r\n", Some("invite.ics")), "text/calendar; charset=us-ascii");
+            assert_eq!(sniff(b"{}", Some("data.json")), "application/json");
+        }
+
+        #[test]
+        fn falls_back_to_octet_stream() {
+            assert_eq!(sniff(b"\x00\x01\x02\x03", None), "application/octet-stream");
+            assert_eq!(sniff(b"\xff\xfe\x00\x41", Some("file.txt")), "application/octet-stream");
+        }
+    }
+
     mod write_random_boundary_to {
         use super::super::*;
 
@@ -0,0 +1,870 @@
+//! Module containing the parsing of encoded mails back into a `Mail`.
+//!
+//! The parser rebuilds the (multipart) structure of a mail based on the
+//! `Content-Type` headers and their `boundary` parameter. Leaf bodies are
+//! _not_ transfer decoded, instead they are returned as `Resource::EncData`
+//! using the encoding specified by the `Content-Transfer-Encoding` header.
+//!
+//! Only headers which can be represented with the header implementations
+//! currently available are kept, all other headers are skipped (and
+//! a warning is logged). Use `Mail::parse_with_skipped_headers` to get
+//! the skipped headers.
+use std::str;
+
+use chrono;
+use soft_ascii_string::SoftAsciiString;
+use vec1::Vec1;
+use media_type::BOUNDARY;
+
+use internals::bind::base64;
+
+use headers::{
+    headers,
+    HeaderTryFrom,
+    header_components::{
+        MediaType,
+        Mailbox,
+        MailboxList,
+        Email,
+        Phrase,
+        MessageId,
+        MessageIdList,
+        ContentId,
+        FileMeta,
+        Disposition,
+        DispositionKind,
+        TransferEncoding
+    }
+};
+
+use ::{
+    error::{MailError, ParsingError, OtherValidationError},
+    resource::{Resource, EncData, Metadata},
+    mail::Mail,
+    context::Context
+};
+
+impl Mail {
+
+    /// Parses a encoded mail (e.g. the content of a `.eml` file) into a `Mail`.
+    ///
+    /// Multipart bodies are split up based on the `boundary` parameter of
+    /// their `Content-Type` header and are parsed recursively. A multipart
+    /// preamble and epilogue are kept.
+    ///
+    /// Non-multipart bodies are returned as `Resource::EncData` with the
+    /// media type from the `Content-Type` header (defaulting to
+    /// `text/plain; charset=us-ascii`) and the transfer encoding from the
+    /// `Content-Transfer-Encoding` header (defaulting to `7bit`). The
+    /// `Context` is used to generate a content id for bodies which don't
+    /// have a `Content-Id` header.
+    ///
+    /// Following headers are currently kept: `Content-Type` (of multipart
+    /// bodies), `Content-Id`, `Content-Disposition` (kind and file name),
+    /// `Date`, `Message-Id`, `Subject`, `Comments`, `From`, `Sender`, `To`,
+    /// `Cc`, `Reply-To`, `In-Reply-To` and `References`. Any other header is
+    /// skipped as there is no way to represent it in a `HeaderMap` yet. The
+    /// same is true for address headers using features not supported by the
+    /// parser (e.g. groups, comments or encoded words) and malformed `Date`
+    /// headers.
+    ///
+    /// Both `\r\n` and `\n` line endings are accepted.
+    pub fn parse(raw: &[u8], ctx: &impl Context) -> Result<Mail, MailError> {
+        Self::parse_with_skipped_headers(raw, ctx)
+            .map(|(mail, _skipped)| mail)
+    }
+
+    /// Like `Mail::parse` but also returns all headers which were skipped.
+    pub fn parse_with_skipped_headers(raw: &[u8], ctx: &impl Context)
+        -> Result<(Mail, Vec<SkippedHeader>), MailError>
+    {
+        let mut skipped = Vec::new();
+        let mail = parse_mail(raw, ctx, &[], &mut skipped)?;
+        Ok((mail, skipped))
+    }
+}
+
+/// A header which was skipped while parsing a mail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedHeader {
+    /// The position of the (sub-)mail the header belongs to in the mime
+    /// tree of the mail, empty for the outer mail.
+    pub position: Vec<usize>,
+
+    /// The name of the header.
+    pub name: String,
+
+    /// The (unfolded) value of the header.
+    pub value: String
+}
+
+fn parse_mail(
+    raw: &[u8],
+    ctx: &impl Context,
+    position: &[usize],
+    skipped: &mut Vec<SkippedHeader>
+) -> Result<Mail, MailError> {
+    let (fields, body) = parse_header_block(raw)?;
+
+    let mut media_type = None;
+    let mut encoding = None;
+    let mut content_id = None;
+    let mut disposition = None;
+    let mut other_fields = Vec::new();
+
+    for (name, value) in fields {
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("Content-Type") {
+            let parsed = MediaType::parse(value)
+                .map_err(|_| ParsingError::InvalidContentType)?;
+            media_type = Some(parsed);
+        } else if name.eq_ignore_ascii_case("Content-Transfer-Encoding") {
+            encoding = Some(parse_transfer_encoding(value)?);
+        } else if name.eq_ignore_ascii_case("Content-Id") {
+            content_id = Some(parse_content_id(value));
+        } else if name.eq_ignore_ascii_case("Content-Disposition") {
+            disposition = Some(parse_disposition(value)?);
+        } else if name.eq_ignore_ascii_case("MIME-Version") {
+            // always generated by the encoder
+        } else {
+            other_fields.push((name, value.to_owned()));
+        }
+    }
+
+    let media_type = match media_type {
+        Some(media_type) => media_type,
+        None => MediaType::parse("text/plain; charset=us-ascii").unwrap()
+    };
+
+    let mut mail =
+        if media_type.is_multipart() {
+            let boundary: String = media_type.get_param(BOUNDARY)
+                .ok_or(ParsingError::MissingBoundary)?
+                .to_content()
+                .into();
+
+            let (preamble, raw_bodies, epilogue) = split_multipart_body(body, &boundary)?;
+            let mut bodies = Vec::with_capacity(raw_bodies.len());
+            for (idx, raw_body) in raw_bodies.into_iter().enumerate() {
+                let mut sub_position = position.to_vec();
+                sub_position.push(idx);
+                bodies.push(parse_mail(raw_body, ctx, &sub_position, skipped)?);
+            }
+            let bodies = Vec1::try_from_vec(bodies)
+                .map_err(|_| OtherValidationError::EmptyMultipartBody)?;
+
+            let mut mail = Mail::new_multipart_mail(media_type, bodies);
+            mail.set_preamble(ascii_text_or_warn(preamble, "preamble"))?;
+            mail.set_epilogue(ascii_text_or_warn(epilogue, "epilogue"))?;
+            mail
+        } else {
+            let mut file_meta = FileMeta::default();
+            if let Some((_, Some(ref file_name))) = disposition {
+                file_meta.file_name = Some(file_name.clone());
+            }
+            let meta = Metadata {
+                file_meta,
+                media_type,
+                content_id: content_id.clone()
+                    .unwrap_or_else(|| ctx.generate_content_id()),
+                source: None,
+                transfer_encoding_hint: Default::default()
+            };
+            let encoding = encoding.unwrap_or(TransferEncoding::_7Bit);
+            let enc_data = EncData::new(body.to_vec(), meta, encoding);
+            Mail::new_singlepart_mail(Resource::EncData(enc_data))
+        };
+
+    if let Some(content_id) = content_id {
+        mail.insert_header(headers::ContentId::body(content_id));
+    }
+
+    if let Some((kind, file_name)) = disposition {
+        let mut file_meta = FileMeta::default();
+        file_meta.file_name = file_name;
+        mail.insert_header(headers::ContentDisposition::body(Disposition::new(kind, file_meta)));
+    }
+
+    for (name, value) in other_fields {
+        if !insert_other_header(&mut mail, &name, &value)? {
+            warn!("skipping unsupported header while parsing mail: {:?}", name);
+            skipped.push(SkippedHeader { position: position.to_vec(), name, value });
+        }
+    }
+
+    Ok(mail)
+}
+
+/// Inserts headers which are not relevant for the mails structure.
+///
+/// Returns `false` if the header is not supported, or it is a date, address
+/// or message id list header which could not be parsed, or a unstructured
+/// header with encoded words which can't be decoded.
+///
+/// Malformed dates are common in bounced or archived mails, so they don't
+/// fail parsing the whole mail.
+fn insert_other_header(mail: &mut Mail, name: &str, value: &str) -> Result<bool, MailError> {
+    match &*name.to_ascii_lowercase() {
+        "subject" => match decode_encoded_words(value) {
+            Some(text) => mail.insert_header(headers::Subject::auto_body(&*text)?),
+            None => return Ok(false)
+        },
+        "comments" => match decode_encoded_words(value) {
+            Some(text) => mail.insert_header(headers::Comments::auto_body(&*text)?),
+            None => return Ok(false)
+        },
+        "date" => {
+            match chrono::DateTime::parse_from_rfc2822(value) {
+                Ok(date) => mail.insert_header(headers::Date::auto_body(date.with_timezone(&chrono::Utc))?),
+                Err(_) => return Ok(false)
+            }
+        },
+        "message-id" => {
+            let msg_id = MessageId::from_unchecked(strip_angle_brackets(value).to_owned());
+            mail.insert_header(headers::MessageId::body(msg_id));
+        },
+        "from" => match parse_mailbox_list(value) {
+            Some(list) => mail.insert_header(headers::_From::body(list)),
+            None => return Ok(false)
+        },
+        "sender" => match parse_mailbox(value) {
+            Some(mailbox) => mail.insert_header(headers::Sender::body(mailbox)),
+            None => return Ok(false)
+        },
+        "to" => match parse_mailbox_list(value) {
+            Some(list) => mail.insert_header(headers::_To::body(list)),
+            None => return Ok(false)
+        },
+        "cc" => match parse_mailbox_list(value) {
+            Some(list) => mail.insert_header(headers::_Cc::body(list)),
+            None => return Ok(false)
+        },
+        "reply-to" => match parse_mailbox_list(value) {
+            Some(list) => mail.insert_header(headers::ReplyTo::body(list)),
+            None => return Ok(false)
+        },
+        "in-reply-to" => match parse_message_id_list(value) {
+            Some(list) => mail.insert_header(headers::InReplyTo::body(list)),
+            None => return Ok(false)
+        },
+        "references" => match parse_message_id_list(value) {
+            Some(list) => mail.insert_header(headers::References::body(list)),
+            None => return Ok(false)
+        },
+        _ => return Ok(false)
+    }
+    Ok(true)
+}
+
+/// Parses a comma separated list of mailboxes.
+fn parse_mailbox_list(value: &str) -> Option<MailboxList> {
+    let mailboxes = split_address_list(value)
+        .into_iter()
+        .map(parse_mailbox)
+        .collect::<Option<Vec<_>>>()?;
+
+    Vec1::try_from_vec(mailboxes).ok().map(MailboxList)
+}
+
+/// Parses a mailbox, i.e. either `addr@spec` or `display name <addr@spec>`.
+///
+/// The display name can be a quoted string, but comments and
+/// encoded words are not supported.
+fn parse_mailbox(value: &str) -> Option<Mailbox> {
+    let value = value.trim();
+    let (display_name, email) =
+        match value.rfind('<') {
+            Some(idx) if value.ends_with('>') => (value[..idx].trim(), &value[idx+1..value.len()-1]),
+            _ => ("", value)
+        };
+
+    let display_name =
+        if display_name.is_empty() {
+            None
+        } else {
+            Some(Phrase::try_from(&*unquote(display_name)).ok()?)
+        };
+    let email = Email::try_from(email.trim()).ok()?;
+
+    Some(Mailbox { display_name, email })
+}
+
+/// Splits a address list at commas which are not in a quoted string or angle brackets.
+fn split_address_list(value: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut in_quotes = false;
+    let mut in_angle_brackets = false;
+    let mut escaped = false;
+
+    for (idx, ch) in value.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match ch {
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            '<' if !in_quotes => in_angle_brackets = true,
+            '>' if !in_quotes => in_angle_brackets = false,
+            ',' if !in_quotes && !in_angle_brackets => {
+                parts.push(&value[start..idx]);
+                start = idx + 1;
+            },
+            _ => {}
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+/// Parses a whitespace separated list of message ids in angle brackets.
+fn parse_message_id_list(value: &str) -> Option<MessageIdList> {
+    let mut ids = Vec::new();
+    let mut rest = value.trim();
+    while !rest.is_empty() {
+        if !rest.starts_with('<') {
+            return None;
+        }
+        let end = rest.find('>')?;
+        ids.push(MessageId::from_unchecked(rest[1..end].to_owned()));
+        rest = rest[end+1..].trim_start();
+    }
+
+    Vec1::try_from_vec(ids).ok().map(MessageIdList)
+}
+
+/// Parses the header block returning all header fields and the remaining body.
+///
+/// Folded header lines are unfolded (the line break is removed).
+fn parse_header_block(raw: &[u8]) -> Result<(Vec<(String, String)>, &[u8]), ParsingError> {
+    let mut fields: Vec<(String, String)> = Vec::new();
+    let mut rest = raw;
+    while !rest.is_empty() {
+        let (line, tail) = split_line(rest);
+        rest = tail;
+
+        if line.is_empty() {
+            break;
+        }
+
+        let line = str::from_utf8(line)
+            .map_err(|_| ParsingError::InvalidHeaderEncoding)?;
+
+        if line.starts_with(' ') || line.starts_with('\t') {
+            match fields.last_mut() {
+                Some(field) => field.1.push_str(line),
+                None => return Err(ParsingError::MalformedHeader)
+            }
+        } else {
+            let colon_idx = line.find(':')
+                .ok_or(ParsingError::MalformedHeader)?;
+            let name = line[..colon_idx].trim_end();
+            if name.is_empty() || name.contains(' ') {
+                return Err(ParsingError::MalformedHeader);
+            }
+            fields.push((name.to_owned(), line[colon_idx+1..].to_owned()));
+        }
+    }
+    Ok((fields, rest))
+}
+
+/// Splits a multipart body into it's preamble, the raw bodies and it's epilogue.
+///
+/// The line break before a delimiter line is part of the delimiter and
+/// as such not included in the body preceding it. Transport padding
+/// (whitespace) after a boundary delimiter is ignored.
+fn split_multipart_body<'a>(body: &'a [u8], boundary: &str)
+    -> Result<(&'a [u8], Vec<&'a [u8]>, &'a [u8]), ParsingError>
+{
+    let dash_boundary = format!("--{}", boundary);
+    let mut preamble: &[u8] = &[];
+    let mut bodies = Vec::new();
+    let mut body_start = None;
+    let mut prev_line_end = 0;
+    let mut pos = 0;
+
+    while pos < body.len() {
+        let (line, rest) = split_line(&body[pos..]);
+        let next_pos = body.len() - rest.len();
+
+        if let Some(is_close_delimiter) = delimiter_kind(line, dash_boundary.as_bytes()) {
+            match body_start {
+                None => preamble = &body[..prev_line_end],
+                Some(start) => {
+                    let end = if prev_line_end < start { start } else { prev_line_end };
+                    bodies.push(&body[start..end]);
+                }
+            }
+
+            if is_close_delimiter {
+                let epilogue = strip_trailing_line_break(&body[next_pos..]);
+                return Ok((preamble, bodies, epilogue));
+            }
+            body_start = Some(next_pos);
+        }
+
+        prev_line_end = pos + line.len();
+        pos = next_pos;
+    }
+
+    Err(ParsingError::MissingClosingBoundary)
+}
+
+/// Returns `Some(is_close_delimiter)` if the line is a boundary delimiter line.
+fn delimiter_kind(line: &[u8], dash_boundary: &[u8]) -> Option<bool> {
+    if !line.starts_with(dash_boundary) {
+        return None;
+    }
+    let rest = &line[dash_boundary.len()..];
+    let (is_close_delimiter, padding) =
+        if rest.starts_with(b"--") {
+            (true, &rest[2..])
+        } else {
+            (false, rest)
+        };
+
+    if padding.iter().all(|&bch| bch == b' ' || bch == b'\t') {
+        Some(is_close_delimiter)
+    } else {
+        None
+    }
+}
+
+fn strip_trailing_line_break(input: &[u8]) -> &[u8] {
+    if input.ends_with(b"\r\n") {
+        &input[..input.len()-2]
+    } else if input.ends_with(b"\n") {
+        &input[..input.len()-1]
+    } else {
+        input
+    }
+}
+
+/// Splits of the first line returning the line (without line break) and the rest.
+fn split_line(input: &[u8]) -> (&[u8], &[u8]) {
+    match input.iter().position(|&bch| bch == b'\n') {
+        Some(idx) => {
+            let line = &input[..idx];
+            let line = if line.ends_with(b"\r") { &line[..idx-1] } else { line };
+            (line, &input[idx+1..])
+        },
+        None => (input, &[])
+    }
+}
+
+fn parse_transfer_encoding(value: &str) -> Result<TransferEncoding, ParsingError> {
+    use self::TransferEncoding::*;
+    let encoding = match &*value.to_ascii_lowercase() {
+        "7bit" => _7Bit,
+        "8bit" => _8Bit,
+        "binary" => Binary,
+        "quoted-printable" => QuotedPrintable,
+        "base64" => Base64,
+        _ => return Err(ParsingError::InvalidContentTransferEncoding)
+    };
+    Ok(encoding)
+}
+
+fn parse_content_id(value: &str) -> ContentId {
+    MessageId::from_unchecked(strip_angle_brackets(value).to_owned()).into()
+}
+
+/// Parses the disposition kind and the `filename` parameter (if there is one).
+///
+/// Parameters using the RFC 2231 extensions are not supported.
+fn parse_disposition(value: &str) -> Result<(DispositionKind, Option<String>), ParsingError> {
+    let mut parts = value.split(';');
+    let kind = match &*parts.next().unwrap_or("").trim().to_ascii_lowercase() {
+        "inline" => DispositionKind::Inline,
+        "attachment" => DispositionKind::Attachment,
+        _ => return Err(ParsingError::InvalidContentDisposition)
+    };
+
+    let mut file_name = None;
+    for param in parts {
+        if let Some(eq_idx) = param.find('=') {
+            if param[..eq_idx].trim().eq_ignore_ascii_case("filename") {
+                file_name = Some(unquote(param[eq_idx+1..].trim()));
+            }
+        }
+    }
+    Ok((kind, file_name))
+}
+
+fn unquote(value: &str) -> String {
+    if !(value.len() >= 2 && value.starts_with('"') && value.ends_with('"')) {
+        return value.to_owned();
+    }
+    let mut out = String::with_capacity(value.len());
+    let mut escaped = false;
+    for ch in value[1..value.len()-1].chars() {
+        if !escaped && ch == '\\' {
+            escaped = true;
+        } else {
+            escaped = false;
+            out.push(ch);
+        }
+    }
+    out
+}
+
+/// Decodes the RFC 2047 encoded words in a unstructured header value.
+///
+/// Whitespace between adjacent encoded words is removed. Only the `utf-8`,
+/// `us-ascii` and `iso-8859-1` charsets are supported, for other charsets
+/// or malformed encoded words `None` is returned.
+fn decode_encoded_words(value: &str) -> Option<String> {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    let mut after_encoded_word = false;
+
+    while let Some(start) = rest.find("=?") {
+        let (before, candidate) = (&rest[..start], &rest[start..]);
+        match split_encoded_word(candidate) {
+            Some((charset, encoding, text, len)) => {
+                if !(after_encoded_word && before.trim().is_empty()) {
+                    out.push_str(before);
+                }
+                out.push_str(&decode_encoded_word(charset, encoding, text)?);
+                rest = &candidate[len..];
+                after_encoded_word = true;
+            },
+            None => {
+                // not a encoded word, just text containing `=?`
+                out.push_str(&rest[..start + 2]);
+                rest = &rest[start + 2..];
+                after_encoded_word = false;
+            }
+        }
+    }
+    out.push_str(rest);
+    Some(out)
+}
+
+/// Splits a `=?charset?encoding?text?=` encoded word at the start of the input.
+///
+/// Returns the charset, encoding, text and the length of the encoded word.
+fn split_encoded_word(input: &str) -> Option<(&str, &str, &str, usize)> {
+    let mut parts = input[2..].splitn(3, '?');
+    let charset = parts.next()?;
+    let encoding = parts.next()?;
+    let rest = parts.next()?;
+    let text = &rest[..rest.find("?=")?];
+
+    let has_whitespace = |part: &str| part.contains(|ch: char| ch.is_whitespace());
+    if charset.is_empty() || encoding.is_empty()
+        || has_whitespace(charset) || has_whitespace(encoding) || has_whitespace(text)
+    {
+        return None;
+    }
+
+    let len = charset.len() + encoding.len() + text.len() + 6;
+    Some((charset, encoding, text, len))
+}
+
+fn decode_encoded_word(charset: &str, encoding: &str, text: &str) -> Option<String> {
+    let bytes =
+        match &*encoding.to_ascii_lowercase() {
+            "b" => base64::normal_decode(text.as_bytes()).ok()?,
+            "q" => q_decode(text)?,
+            _ => return None
+        };
+
+    // the charset can have a RFC 2231 language suffix, e.g. `utf-8*en`
+    let charset = charset.split('*').next().unwrap_or("").to_ascii_lowercase();
+    match &*charset {
+        "utf-8" | "utf8" | "us-ascii" => String::from_utf8(bytes).ok(),
+        "iso-8859-1" | "latin1" => Some(bytes.iter().map(|&bch| bch as char).collect()),
+        _ => None
+    }
+}
+
+/// Decodes the "Q" encoding used by encoded words.
+fn q_decode(text: &str) -> Option<Vec<u8>> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'_' => out.push(b' '),
+            b'=' => {
+                let hex = bytes.get(idx+1..idx+3)?;
+                if !hex.iter().all(|bch| bch.is_ascii_hexdigit()) {
+                    return None;
+                }
+                //UNWRAP_SAFE: we checked that it's two hex digits
+                let hex = str::from_utf8(hex).unwrap();
+                out.push(u8::from_str_radix(hex, 16).unwrap());
+                idx += 2;
+            },
+            bch => out.push(bch)
+        }
+        idx += 1;
+    }
+    Some(out)
+}
+
+fn strip_angle_brackets(value: &str) -> &str {
+    let value = value.trim();
+    if value.starts_with('<') && value.ends_with('>') && value.len() >= 2 {
+        &value[1..value.len()-1]
+    } else {
+        value
+    }
+}
+
+fn ascii_text_or_warn(raw: &[u8], what: &str) -> SoftAsciiString {
+    let text = String::from_utf8(raw.to_vec()).ok()
+        .and_then(|text| SoftAsciiString::from_string(text).ok());
+
+    match text {
+        Some(text) => text,
+        None => {
+            warn!("dropping non us-ascii multipart {} while parsing mail", what);
+            SoftAsciiString::new()
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use headers::headers::{
+        Subject, Date, ContentType, ContentId as ContentIdHeader,
+        _From, _To, _Cc, ReplyTo, InReplyTo
+    };
+    use futures::Future;
+    use internals::MailType;
+    use default_impl::test_context;
+    use mail::MailBody;
+    use super::*;
+
+    fn leaf_data(mail: &Mail) -> &EncData {
+        match mail.body() {
+            &MailBody::SingleBody { body: Resource::EncData(ref enc_data) } => enc_data,
+            other => panic!("unexpected body: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn parses_singlepart_mail() {
+        let ctx = test_context();
+        let raw = concat!(
+            "Subject: hy there\r\n",
+            "Content-Type: text/plain; charset=utf-8\r\n",
+            "Content-Transfer-Encoding: quoted-printable\r\n",
+            "\r\n",
+            "abc=3D\r\n"
+        );
+
+        let mail = assert_ok!(Mail::parse(raw.as_bytes(), &ctx));
+
+        assert!(mail.headers().contains(Subject));
+        assert_not!(mail.headers().contains(ContentType));
+        let data = leaf_data(&mail);
+        assert_eq!(data.encoding(), TransferEncoding::QuotedPrintable);
+        assert_eq!(data.media_type().as_str_repr(), "text/plain; charset=utf-8");
+        assert_eq!(&**data.transfer_encoded_buffer(), b"abc=3D\r\n");
+    }
+
+    #[test]
+    fn parses_nested_multipart_mail() {
+        let ctx = test_context();
+        let raw = concat!(
+            "Content-Type: multipart/mixed; boundary=\"=_^0.outer\"\n",
+            "\n",
+            "This is a multi-part message in MIME format.\n",
+            "--=_^0.outer\n",
+            "Content-Type: multipart/alternative; boundary=\"=_^1.inner\"\n",
+            "\n",
+            "--=_^1.inner\n",
+            "\n",
+            "plain\n",
+            "--=_^1.inner\n",
+            "Content-Type: text/html\n",
+            "\n",
+            "<b>html</b>\n",
+            "--=_^1.inner--\n",
+            "--=_^0.outer\n",
+            "Content-Id: <a1@example.test>\n",
+            "Content-Disposition: attachment; filename=\"a b.txt\"\n",
+            "Content-Transfer-Encoding: base64\n",
+            "\n",
+            "YWI=\n",
+            "--=_^0.outer--\n",
+            "epilogue\n"
+        );
+
+        let mail = assert_ok!(Mail::parse(raw.as_bytes(), &ctx));
+
+        let (outer, hidden_text, epilogue) = match mail.body() {
+            &MailBody::MultipleBodies { ref bodies, ref hidden_text, ref epilogue } =>
+                (bodies, hidden_text, epilogue),
+            _ => panic!("expected multipart body")
+        };
+        assert_eq!(hidden_text.as_str(), "This is a multi-part message in MIME format.");
+        assert_eq!(epilogue.as_str(), "epilogue");
+        assert_eq!(outer.len(), 2);
+
+        match outer[0].body() {
+            &MailBody::MultipleBodies { ref bodies, .. } => {
+                assert_eq!(bodies.len(), 2);
+                assert_eq!(&**leaf_data(&bodies[0]).transfer_encoded_buffer(), b"plain");
+                assert_eq!(leaf_data(&bodies[1]).media_type().as_str_repr(), "text/html");
+            },
+            _ => panic!("expected multipart body")
+        }
+
+        let attachment = &outer[1];
+        assert!(attachment.headers().contains(ContentIdHeader));
+        let data = leaf_data(attachment);
+        assert_eq!(data.encoding(), TransferEncoding::Base64);
+        assert_eq!(data.file_meta().file_name, Some("a b.txt".to_owned()));
+        assert_eq!(&**data.transfer_encoded_buffer(), b"YWI=");
+    }
+
+    #[test]
+    fn unfolds_headers() {
+        let (fields, body) = assert_ok!(parse_header_block(b"Subject: a\r\n  b\r\n\r\nbody"));
+        assert_eq!(fields, vec![("Subject".to_owned(), " a  b".to_owned())]);
+        assert_eq!(body, b"body");
+    }
+
+    #[test]
+    fn multipart_without_closing_delimiter_fails() {
+        let res = split_multipart_body(b"--b\r\n\r\nbody\r\n", "b");
+        assert_eq!(assert_err!(res), ParsingError::MissingClosingBoundary);
+    }
+
+    #[test]
+    fn multipart_without_boundary_fails() {
+        let ctx = test_context();
+        let raw = b"Content-Type: multipart/mixed\r\n\r\n--b\r\n\r\nx\r\n--b--\r\n";
+        match assert_err!(Mail::parse(raw, &ctx)) {
+            MailError::Parsing(ParsingError::MissingBoundary) => {},
+            other => panic!("unexpected error: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn multipart_without_bodies_fails() {
+        let ctx = test_context();
+        let raw = b"Content-Type: multipart/mixed; boundary=b\r\n\r\n--b--\r\n";
+        assert_err!(Mail::parse(raw, &ctx));
+    }
+
+    #[test]
+    fn parses_address_headers() {
+        let ctx = test_context();
+        let raw = concat!(
+            "From: \"Doe, John\" <john@example.test>\r\n",
+            "To: a@example.test, B <b@example.test>\r\n",
+            "Cc: c@example.test\r\n",
+            "Reply-To: <reply@example.test>\r\n",
+            "In-Reply-To: <a1@example.test>\r\n",
+            "\r\n",
+            "body\r\n"
+        );
+
+        let (mail, skipped) = assert_ok!(Mail::parse_with_skipped_headers(raw.as_bytes(), &ctx));
+        assert!(skipped.is_empty());
+
+        let headers = mail.headers();
+        assert!(headers.contains(_From));
+        assert!(headers.contains(_To));
+        assert!(headers.contains(_Cc));
+        assert!(headers.contains(ReplyTo));
+        assert!(headers.contains(InReplyTo));
+
+        let from = headers.get_single(_From).unwrap().unwrap();
+        assert_eq!(from.0.len(), 1);
+        let to = headers.get_single(_To).unwrap().unwrap();
+        assert_eq!(to.0.len(), 2);
+    }
+
+    test!(decodes_encoded_words_in_subjects, {
+        let ctx = test_context();
+        let raw = concat!(
+            "From: a@example.test\r\n",
+            "Subject: =?utf-8?q?h=C3=BCy_?=\r\n =?UTF-8?B?dGhlcmU=?=\r\n",
+            "\r\n",
+            "body\r\n"
+        );
+
+        let mail = Mail::parse(raw.as_bytes(), &ctx)?;
+        let subject = mail.headers().get_single(Subject).unwrap()?;
+        assert!(format!("{:?}", subject).contains("hüy there"));
+
+        // the subject is encoded again (not double encoded) when sending the mail
+        let encoded = mail.into_encodable_mail(ctx.clone()).wait()?
+            .encode_into_bytes(MailType::Ascii)?;
+        let mail = Mail::parse(&encoded, &ctx)?;
+        let subject = mail.headers().get_single(Subject).unwrap()?;
+        assert!(format!("{:?}", subject).contains("hüy there"));
+    });
+
+    #[test]
+    fn skips_unstructured_headers_with_unsupported_charsets() {
+        let ctx = test_context();
+        let raw = b"Subject: =?koi8-r?b?8NLJ18XU?=\r\n\r\nbody\r\n";
+
+        let (mail, skipped) = assert_ok!(Mail::parse_with_skipped_headers(raw, &ctx));
+        assert_not!(mail.headers().contains(Subject));
+        assert_eq!(skipped.len(), 1);
+    }
+
+    #[test]
+    fn decodes_encoded_words() {
+        assert_eq!(decode_encoded_words("a =?iso-8859-1?q?=FC?= b").unwrap(), "a ü b");
+        assert_eq!(decode_encoded_words("=?utf-8?q?a?= =?utf-8?q?b?=").unwrap(), "ab");
+        assert_eq!(decode_encoded_words("1 =? 2").unwrap(), "1 =? 2");
+        assert_eq!(decode_encoded_words("=?utf-8?q?=ZZ?="), None);
+    }
+
+    #[test]
+    fn skips_malformed_dates() {
+        let ctx = test_context();
+        let raw = b"Date: yesterday at noon\r\nSubject: hy\r\n\r\nbody\r\n";
+
+        let (mail, skipped) = assert_ok!(Mail::parse_with_skipped_headers(raw, &ctx));
+        assert_not!(mail.headers().contains(Date));
+        assert!(mail.headers().contains(Subject));
+        assert_eq!(skipped, vec![
+            SkippedHeader { position: vec![], name: "Date".to_owned(), value: "yesterday at noon".to_owned() }
+        ]);
+    }
+
+    #[test]
+    fn returns_skipped_headers() {
+        let ctx = test_context();
+        let raw = concat!(
+            "Content-Type: multipart/mixed; boundary=b\r\n",
+            "X-Mailer: test\r\n",
+            "To: undisclosed-recipients:;\r\n",
+            "\r\n",
+            "--b\r\n",
+            "X-Custom: inner\r\n",
+            "\r\n",
+            "x\r\n",
+            "--b--\r\n"
+        );
+
+        let (mail, skipped) = assert_ok!(Mail::parse_with_skipped_headers(raw.as_bytes(), &ctx));
+        assert_not!(mail.headers().contains(_To));
+        // sub-parts are parsed before the headers of the outer mail are inserted
+        assert_eq!(skipped, vec![
+            SkippedHeader { position: vec![0], name: "X-Custom".to_owned(), value: "inner".to_owned() },
+            SkippedHeader { position: vec![], name: "X-Mailer".to_owned(), value: "test".to_owned() },
+            SkippedHeader { position: vec![], name: "To".to_owned(), value: "undisclosed-recipients:;".to_owned() },
+        ]);
+    }
+
+    #[test]
+    fn splits_address_lists_outside_of_quotes_and_brackets() {
+        assert_eq!(
+            split_address_list("\"a, b\" <a@b.test>, <c,d@e.test>, f@g.test"),
+            vec!["\"a, b\" <a@b.test>", " <c,d@e.test>", " f@g.test"]
+        );
+    }
+}
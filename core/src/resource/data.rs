@@ -1,4 +1,5 @@
 use std::{
+    str,
     sync::Arc,
     default::Default,
     ops::{Deref, DerefMut}
@@ -11,7 +12,11 @@ use serde::{
     de::{Deserializer}
 };
 
-use internals::bind::{base64, quoted_printable};
+use internals::{
+    MailType,
+    bind::{base64, quoted_printable},
+    error::{EncodingError, EncodingErrorKind, Place}
+};
 use headers::header_components::{
     MediaType,
     FileMeta,
@@ -19,9 +24,10 @@ use headers::header_components::{
     ContentId
 };
 
+use super::Source;
 
 
-/// POD type containing FileMeta, Content-Type and Content-Id
+/// POD type containing FileMeta, Content-Type, Content-Id and the Source
 ///
 /// The file meta contains optional information like file name and read
 /// as well as last modification data.
@@ -47,7 +53,21 @@ pub struct Metadata {
     pub media_type: MediaType,
 
     /// The content id associated with the data.
-    pub content_id: ContentId
+    pub content_id: ContentId,
+
+    /// The source the data was loaded from, if any.
+    ///
+    /// This is set by the resource loaders and allows unloading
+    /// the data again (see `Resource::unload`).
+    #[cfg_attr(feature="serde", serde(default))]
+    pub source: Option<Source>,
+
+    /// The preferred way to transfer encode the data.
+    ///
+    /// This is used by `Context::transfer_encode_resource` (and the
+    /// resource loaders) when transfer encoding the data.
+    #[cfg_attr(feature="serde", serde(default))]
+    pub transfer_encoding_hint: TransferEncodingHint
 }
 
 impl Deref for Metadata {
@@ -108,7 +128,9 @@ impl Data {
         let meta = Metadata {
             file_meta: Default::default(),
             media_type: MediaType::parse("text/plain; charset=utf-8").unwrap(),
-            content_id: cid
+            content_id: cid,
+            source: None,
+            transfer_encoding_hint: Default::default()
         };
         Self::new(buf, meta)
     }
@@ -138,6 +160,11 @@ impl Data {
         &self.meta.content_id
     }
 
+    /// Access the preferred transfer encoding hint.
+    pub fn transfer_encoding_hint(&self) -> TransferEncodingHint {
+        self.meta.transfer_encoding_hint
+    }
+
     /// Transfer encode the given data.
     ///
     /// This function will be called by the context implementation when
@@ -238,10 +265,32 @@ impl EncData {
     pub fn content_id(&self) -> &ContentId {
         &self.meta.content_id
     }
+
+    /// Transfer decodes the data.
+    ///
+    /// This reverses the transfer encoding returning a `Data` instance
+    /// which shares the metadata with this instance. It can be used to
+    /// e.g. inspect a body or to re-encode it with a different
+    /// `TransferEncodingHint`.
+    ///
+    /// Data using the `7bit`, `8bit` or `binary` transfer encoding is
+    /// returned as is (line endings are not changed).
+    ///
+    /// # Error
+    ///
+    /// Fails if the buffer is not valid wrt. the used transfer encoding,
+    /// e.g. if it is malformed base64 or `7bit` data containing non us-ascii
+    /// bytes.
+    #[inline(always)]
+    pub fn transfer_decode(&self) -> Result<Data, EncodingError> {
+        // delegated to free function at end of file for
+        // readability
+        transfer_decode(self)
+    }
 }
 
 /// Hint to change how data should be transfer encoded.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[cfg_attr(feature="serde", derive(Serialize, Deserialize))]
 pub enum TransferEncodingHint {
     /// Use Base64 encoding.
@@ -250,6 +299,43 @@ pub enum TransferEncodingHint {
     /// Use Quoted-Printable encoding.
     UseQuotedPrintable,
 
+    /// Choose the transfer encoding based on the content.
+    ///
+    /// - `7bit` is used for us-ascii data which doesn't breach the
+    ///   line length limit (line endings of `text/*` data are
+    ///   normalized to CRLF)
+    /// - `quoted-printable` is used for mostly us-ascii text
+    /// - `base64` is used for everything else, e.g. binary data
+    ///   or non latin text
+    ///
+    /// As the transfer encoding is choosen before it is known with
+    /// which `MailType` the mail will be encoded `8bit` is never used.
+    /// Through if the mail is encoded with a `MailType` supporting 8bit
+    /// bodies text encoded this way is re-encoded using `auto_for`.
+    Auto,
+
+    /// Like `Auto` but uses `8bit` for utf-8 text (which doesn't breach
+    /// the line length limit) instead of `quoted-printable`/`base64`.
+    ///
+    /// This should only be used if the mail will be encoded with a
+    /// `MailType` which supports 8bit bodies (i.e. `Mime8BitEnabled`
+    /// or `Internationalized`), use `TransferEncodingHint::auto_for`
+    /// to pick the right auto mode for a mail type.
+    AutoAllow8Bit,
+
+    /// Do not transfer encode the data.
+    ///
+    /// The data is used "as is" (except for normalizing the line endings
+    /// of `text/*` data) with `7bit` for us-ascii data which doesn't
+    /// breach the line length limit, `8bit` for other data which
+    /// doesn't breach it and `binary` else wise.
+    ///
+    /// If the `MailType` used to encode the mail doesn't support the
+    /// resulting transfer encoding the data is re-encoded when encoding
+    /// the mail. So this is mainly useful for data already known to be
+    /// fine, e.g. a pre-wrapped us-ascii report.
+    NoEncoding,
+
     // /// Do not assume Mime8Bit is available.
     // ///
     // /// As such do not encode ascii/utf-8 "as is" (e.g. not encoding them).
@@ -258,7 +344,7 @@ pub enum TransferEncodingHint {
     // /// with puthing things in unecoded.
     // DoNotUseNoEncoding,
 
-    /// No hint for transfer encoding.
+    /// No hint for transfer encoding, this behaves like `Auto`.
     NoHint,
 
     #[cfg_attr(feature="serde", serde(skip))]
@@ -266,17 +352,34 @@ pub enum TransferEncodingHint {
     __NonExhaustive { }
 }
 
+impl TransferEncodingHint {
+
+    /// Returns the auto hint appropriate for given mail type.
+    ///
+    /// This is `AutoAllow8Bit` if the mail type supports 8bit
+    /// bodies and `Auto` else wise.
+    pub fn auto_for(mail_type: MailType) -> Self {
+        match mail_type {
+            MailType::Ascii => TransferEncodingHint::Auto,
+            MailType::Mime8BitEnabled | MailType::Internationalized =>
+                TransferEncodingHint::AutoAllow8Bit
+        }
+    }
+}
+
 impl Default for TransferEncodingHint {
     fn default() -> Self {
-        TransferEncodingHint::UseBase64
+        TransferEncodingHint::Auto
     }
 }
 
+/// The maximal line length (excluding CRLF) allowed by RFC 5322.
+const LINE_LEN_HARD_LIMIT: usize = 998;
+
 /// Transfer encodes Data.
 ///
-/// Util we have a reasonable "non latin letter text" heuristic
-/// or enable none encoded text as default this will always encode
-/// with `Base64` except if asked not to do so.
+/// See `TransferEncodingHint::Auto` for how the transfer encoding
+/// is choosen if it isn't explicitly specified.
 ///
 /// # Panic
 ///
@@ -290,11 +393,155 @@ fn transfer_encode(
 
     match encoding_hint {
         UseQuotedPrintable => tenc_quoted_printable(data),
-        UseBase64 | NoHint => tenc_base64(data),
+        UseBase64 => tenc_base64(data),
+        Auto | NoHint => tenc_auto(data, false),
+        AutoAllow8Bit => tenc_auto(data, true),
+        NoEncoding => tenc_none(data),
         __NonExhaustive { .. } => panic!("__NonExhaustive encoding should not be passed to any place")
     }
 }
 
+fn tenc_auto(data: &Data, allow_8bit: bool) -> EncData {
+    let buffer = data.buffer();
+    let stats = ContentStats::from_buffer(buffer);
+    let is_text = is_text_media_type(data.media_type());
+
+    if is_text {
+        let fits_lines = stats.max_line_len <= LINE_LEN_HARD_LIMIT && !stats.has_nul;
+        if fits_lines && stats.non_ascii == 0 {
+            tenc_unencoded(data, TransferEncoding::_7Bit)
+        } else if fits_lines && allow_8bit && str::from_utf8(buffer).is_ok() {
+            tenc_unencoded(data, TransferEncoding::_8Bit)
+        } else if stats.non_ascii * 6 < buffer.len() {
+            tenc_quoted_printable(data)
+        } else {
+            tenc_base64(data)
+        }
+    } else {
+        // non text data can not have it's line endings normalized
+        let is_7bit_clean = stats.non_ascii == 0
+            && !stats.has_nul
+            && !stats.has_bare_line_break
+            && stats.max_line_len <= LINE_LEN_HARD_LIMIT;
+
+        if is_7bit_clean {
+            EncData::new(buffer.clone(), data.metadata().clone(),
+                TransferEncoding::_7Bit)
+        } else {
+            tenc_base64(data)
+        }
+    }
+}
+
+fn tenc_none(data: &Data) -> EncData {
+    let buffer = data.buffer();
+    let stats = ContentStats::from_buffer(buffer);
+    let is_text = is_text_media_type(data.media_type());
+
+    // non text data can not have it's line endings normalized
+    let fits_lines = stats.max_line_len <= LINE_LEN_HARD_LIMIT
+        && !stats.has_nul
+        && (is_text || !stats.has_bare_line_break);
+
+    if !fits_lines {
+        return EncData::new(buffer.clone(), data.metadata().clone(),
+            TransferEncoding::Binary);
+    }
+
+    let encoding =
+        if stats.non_ascii == 0 {
+            TransferEncoding::_7Bit
+        } else {
+            TransferEncoding::_8Bit
+        };
+
+    if is_text {
+        tenc_unencoded(data, encoding)
+    } else {
+        EncData::new(buffer.clone(), data.metadata().clone(), encoding)
+    }
+}
+
+/// Uses the data "as is" except for normalizing line endings to CRLF.
+fn tenc_unencoded(data: &Data, encoding: TransferEncoding) -> EncData {
+    let buffer = data.buffer();
+    let mut out = Vec::with_capacity(buffer.len());
+    let mut iter = buffer.iter().cloned().peekable();
+    while let Some(bch) = iter.next() {
+        match bch {
+            b'\r' => {
+                if iter.peek() == Some(&b'\n') {
+                    iter.next();
+                }
+                out.extend_from_slice(b"\r\n");
+            },
+            b'\n' => out.extend_from_slice(b"\r\n"),
+            bch => out.push(bch)
+        }
+    }
+
+    EncData::new(out, data.metadata().clone(), encoding)
+}
+
+pub(crate) fn is_text_media_type(media_type: &MediaType) -> bool {
+    let repr = media_type.as_str_repr();
+    repr.len() >= 5 && repr[..5].eq_ignore_ascii_case("text/")
+}
+
+/// Statistics about a buffer used to choose a transfer encoding.
+struct ContentStats {
+    /// Number of bytes which are not us-ascii.
+    non_ascii: usize,
+    /// Whether or not the buffer contains a `\0` byte.
+    has_nul: bool,
+    /// Whether or not the buffer contains a `\r` or `\n` which is not part of a CRLF.
+    has_bare_line_break: bool,
+    /// The length of the longest line (excluding the line break).
+    max_line_len: usize
+}
+
+impl ContentStats {
+
+    fn from_buffer(buffer: &[u8]) -> Self {
+        let mut stats = ContentStats {
+            non_ascii: 0,
+            has_nul: false,
+            has_bare_line_break: false,
+            max_line_len: 0
+        };
+
+        let mut line_len = 0;
+        for (idx, &bch) in buffer.iter().enumerate() {
+            match bch {
+                b'\r' => {
+                    if buffer.get(idx + 1) != Some(&b'\n') {
+                        stats.has_bare_line_break = true;
+                        line_len = 0;
+                    }
+                },
+                b'\n' => {
+                    if idx == 0 || buffer[idx - 1] != b'\r' {
+                        stats.has_bare_line_break = true;
+                    }
+                    line_len = 0;
+                },
+                bch => {
+                    if bch == 0 {
+                        stats.has_nul = true;
+                    } else if bch >= 0x80 {
+                        stats.non_ascii += 1;
+                    }
+                    line_len += 1;
+                    if line_len > stats.max_line_len {
+                        stats.max_line_len = line_len;
+                    }
+                }
+            }
+        }
+        stats
+    }
+}
+
 fn tenc_base64(data: &Data) -> EncData {
     let enc_data = base64::normal_encode(data.buffer())
         .into_bytes();
@@ -312,6 +559,31 @@ fn tenc_quoted_printable(data: &Data) -> EncData {
 }
 
 
+/// Transfer decodes EncData.
+fn transfer_decode(enc_data: &EncData) -> Result<Data, EncodingError> {
+    use self::TransferEncoding::*;
+
+    let encoded = enc_data.transfer_encoded_buffer();
+    let buffer: Arc<[u8]> =
+        match enc_data.encoding() {
+            Base64 => base64::normal_decode(&**encoded)
+                .map_err(|err| err.with_place_or_else(|| Some(Place::Body)))?
+                .into(),
+            QuotedPrintable => quoted_printable::normal_decode(&**encoded)
+                .map_err(|err| err.with_place_or_else(|| Some(Place::Body)))?
+                .into(),
+            _7Bit => {
+                if !encoded.is_ascii() {
+                    return Err(EncodingError::from(EncodingErrorKind::Malformed)
+                        .with_place_or_else(|| Some(Place::Body)));
+                }
+                encoded.clone()
+            },
+            _8Bit | Binary => encoded.clone()
+        };
+
+    Ok(Data::new(buffer, enc_data.metadata().clone()))
+}
 
 mod arc_buffer_serde {
     use super::*;
@@ -345,4 +617,152 @@ mod arc_serde {
     {
         IN::serialize(&**data, serializer)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use context::Context;
+    use default_impl::test_context;
+    use super::*;
+
+    fn data(content: &str) -> Data {
+        Data::plain_text(content, test_context().generate_content_id())
+    }
+
+    #[test]
+    fn base64_round_trip() {
+        let data = data("hy there, ümlaut");
+        let enc_data = data.transfer_encode(TransferEncodingHint::UseBase64);
+        assert_eq!(enc_data.encoding(), TransferEncoding::Base64);
+
+        let decoded = assert_ok!(enc_data.transfer_decode());
+        assert_eq!(decoded.buffer(), data.buffer());
+        assert_eq!(decoded.content_id(), data.content_id());
+    }
+
+    #[test]
+    fn quoted_printable_round_trip() {
+        let data = data("a=b ümlaut");
+        let enc_data = data.transfer_encode(TransferEncodingHint::UseQuotedPrintable);
+        assert_eq!(enc_data.encoding(), TransferEncoding::QuotedPrintable);
+
+        let decoded = assert_ok!(enc_data.transfer_decode());
+        assert_eq!(decoded.buffer(), data.buffer());
+    }
+
+    #[test]
+    fn malformed_base64_is_an_error() {
+        let data = data("");
+        let enc_data = EncData::new(&b"a!b?"[..], data.metadata().clone(), TransferEncoding::Base64);
+        assert_err!(enc_data.transfer_decode());
+    }
+
+    #[test]
+    fn non_ascii_7bit_data_is_an_error() {
+        let data = data("");
+        let enc_data = EncData::new(&b"\xff"[..], data.metadata().clone(), TransferEncoding::_7Bit);
+        assert_err!(enc_data.transfer_decode());
+    }
+
+    fn binary_data(content: &[u8]) -> Data {
+        let meta = Metadata {
+            file_meta: Default::default(),
+            media_type: MediaType::parse("application/octet-stream").unwrap(),
+            content_id: test_context().generate_content_id(),
+            source: None,
+            transfer_encoding_hint: Default::default()
+        };
+        Data::new(content.to_vec(), meta)
+    }
+
+    #[test]
+    fn auto_is_the_default() {
+        assert_eq!(TransferEncodingHint::default(), TransferEncodingHint::Auto);
+    }
+
+    #[test]
+    fn auto_uses_7bit_for_ascii_text() {
+        let data = data("hy there\nhow are you");
+        let enc_data = data.transfer_encode(TransferEncodingHint::Auto);
+        assert_eq!(enc_data.encoding(), TransferEncoding::_7Bit);
+        assert_eq!(&**enc_data.transfer_encoded_buffer(), b"hy there\r\nhow are you");
+    }
+
+    #[test]
+    fn auto_uses_quoted_printable_for_mostly_ascii_text() {
+        let data = data("hy there, mit ümlaut");
+        let enc_data = data.transfer_encode(TransferEncodingHint::Auto);
+        assert_eq!(enc_data.encoding(), TransferEncoding::QuotedPrintable);
+    }
+
+    #[test]
+    fn auto_uses_quoted_printable_for_text_with_too_long_lines() {
+        let data = data(&"a".repeat(1000));
+        let enc_data = data.transfer_encode(TransferEncodingHint::Auto);
+        assert_eq!(enc_data.encoding(), TransferEncoding::QuotedPrintable);
+    }
+
+    #[test]
+    fn auto_uses_base64_for_non_latin_text() {
+        let data = data("Привет, как дела?");
+        let enc_data = data.transfer_encode(TransferEncodingHint::Auto);
+        assert_eq!(enc_data.encoding(), TransferEncoding::Base64);
+    }
+
+    #[test]
+    fn auto_uses_base64_for_binary_data() {
+        let data = binary_data(b"\x89PNG\r\n\x1a\n\0\0");
+        let enc_data = data.transfer_encode(TransferEncodingHint::Auto);
+        assert_eq!(enc_data.encoding(), TransferEncoding::Base64);
+
+        let data = binary_data(b"ascii but\nwith bare line breaks");
+        let enc_data = data.transfer_encode(TransferEncodingHint::Auto);
+        assert_eq!(enc_data.encoding(), TransferEncoding::Base64);
+    }
+
+    #[test]
+    fn auto_allow_8bit_uses_8bit_for_utf8_text() {
+        let data = data("Привет,\nкак дела?");
+        let enc_data = data.transfer_encode(TransferEncodingHint::AutoAllow8Bit);
+        assert_eq!(enc_data.encoding(), TransferEncoding::_8Bit);
+        assert_eq!(&**enc_data.transfer_encoded_buffer(), "Привет,\r\nкак дела?".as_bytes());
+    }
+
+    #[test]
+    fn auto_for_depends_on_mail_type() {
+        assert_eq!(TransferEncodingHint::auto_for(MailType::Ascii), TransferEncodingHint::Auto);
+        assert_eq!(
+            TransferEncodingHint::auto_for(MailType::Mime8BitEnabled),
+            TransferEncodingHint::AutoAllow8Bit
+        );
+    }
+
+    #[test]
+    fn non_encoded_data_is_kept_as_is() {
+        let data = data("");
+        let enc_data = EncData::new(&b"\xff\r\n"[..], data.metadata().clone(), TransferEncoding::Binary);
+        let decoded = assert_ok!(enc_data.transfer_decode());
+        assert_eq!(&**decoded.buffer(), b"\xff\r\n");
+    }
+
+    #[test]
+    fn no_encoding_uses_7bit_or_8bit_for_text() {
+        let enc_data = data("a pre-wrapped\nreport").transfer_encode(TransferEncodingHint::NoEncoding);
+        assert_eq!(enc_data.encoding(), TransferEncoding::_7Bit);
+        assert_eq!(&**enc_data.transfer_encoded_buffer(), b"a pre-wrapped\r\nreport");
+
+        let enc_data = data("mit ümlaut").transfer_encode(TransferEncodingHint::NoEncoding);
+        assert_eq!(enc_data.encoding(), TransferEncoding::_8Bit);
+    }
+
+    #[test]
+    fn no_encoding_uses_binary_if_lines_do_not_fit() {
+        let enc_data = data(&"a".repeat(1000)).transfer_encode(TransferEncodingHint::NoEncoding);
+        assert_eq!(enc_data.encoding(), TransferEncoding::Binary);
+
+        let data = binary_data(b"ascii but\nwith bare line breaks");
+        let enc_data = data.transfer_encode(TransferEncodingHint::NoEncoding);
+        assert_eq!(enc_data.encoding(), TransferEncoding::Binary);
+        assert_eq!(enc_data.transfer_encoded_buffer(), data.buffer());
+    }
+}
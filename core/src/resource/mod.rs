@@ -1,6 +1,7 @@
 // a module level circ. dep. but fine as only
 // used for more ergonomic helper constructors
 use ::context::Context;
+use ::error::ResourceNotUnloadableError;
 
 #[cfg(feature="serde")]
 use serde::{Serialize, Deserialize};
@@ -120,6 +121,7 @@ mod data;
 
 pub use self::source::*;
 pub use self::data::*;
+pub(crate) use self::data::is_text_media_type;
 
 
 
@@ -178,4 +180,96 @@ impl Resource {
     pub fn plain_text(content: impl Into<String>, ctx: &impl Context) -> Resource {
         Resource::Data(Data::plain_text(content, ctx.generate_content_id()))
     }
+
+    /// Returns true if the resource is a `Source` (i.e. it's not loaded).
+    pub fn is_source(&self) -> bool {
+        match *self {
+            Resource::Source(_) => true,
+            _ => false
+        }
+    }
+
+    /// Unloads the resource replacing it with the `Source` it was loaded from.
+    ///
+    /// This drops this resources reference to the (transfer encoded) data,
+    /// it will be reloaded on demand e.g. when turning a mail containing the
+    /// resource into an encodable mail. Other holders of the data (e.g. a
+    /// clone of this resource or a cache) are not affected, i.e. the memory
+    /// is only freed once all of them dropped it. Unloading a `Source` does
+    /// nothing.
+    ///
+    /// # Error
+    ///
+    /// - `ResourceNotUnloadableError::NoSource` if the data wasn't loaded
+    ///   from a source (e.g. it's auto generated), i.e. `Metadata.source` is
+    ///   `None`.
+    pub fn unload(&mut self) -> Result<(), ResourceNotUnloadableError> {
+        let source =
+            match *self {
+                Resource::Source(_) => return Ok(()),
+                Resource::Data(ref data) => data.metadata().source.clone(),
+                Resource::EncData(ref enc_data) => enc_data.metadata().source.clone()
+            };
+
+        let source = source.ok_or(ResourceNotUnloadableError::NoSource)?;
+        *self = Resource::Source(source);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::Future;
+    use default_impl::{test_context, MemResourceLoader};
+    use ::{IRI, context::ResourceLoaderComponent};
+    use super::*;
+
+    fn loaded_resource() -> Resource {
+        let loader = MemResourceLoader::new();
+        let iri = IRI::new("mem:r0").unwrap();
+        loader.register(iri.clone(), Data::plain_text("r0", test_context().generate_content_id()));
+
+        let source = Source {
+            iri,
+            use_media_type: Default::default(),
+            use_file_name: None,
+            use_transfer_encoding: None
+        };
+        let enc_data = loader.load_resource(&source, &test_context()).wait().unwrap();
+        Resource::EncData(enc_data)
+    }
+
+    #[test]
+    fn unloading_replaces_data_with_source() {
+        let mut resource = loaded_resource();
+        assert_ok!(resource.unload());
+        match resource {
+            Resource::Source(ref source) => assert_eq!(source.iri.as_str(), "mem:r0"),
+            other => panic!("unexpected resource: {:?}", other)
+        }
+
+        // unloading a source does nothing
+        assert_ok!(resource.unload());
+        assert!(resource.is_source());
+    }
+
+    #[test]
+    fn unloading_fails_without_source() {
+        let mut resource = Resource::plain_text("r0", &test_context());
+        let err = assert_err!(resource.unload());
+        match err {
+            ResourceNotUnloadableError::NoSource => {},
+            other => panic!("unexpected error: {:?}", other)
+        }
+        assert_not!(resource.is_source());
+    }
+
+    #[test]
+    fn unloading_does_not_affect_other_holders_of_the_data() {
+        let mut resource = loaded_resource();
+        let clone = resource.clone();
+        assert_ok!(resource.unload());
+        assert!(resource.is_source());
+        assert_not!(clone.is_source());
+    }
 }
\ No newline at end of file
@@ -1,9 +1,15 @@
 
+use std::{
+    hash::{Hash, Hasher},
+    mem
+};
+
 use headers::{
     header_components::MediaType
 };
 use ::{
-    iri::IRI
+    iri::IRI,
+    resource::TransferEncodingHint
 };
 
 #[cfg(feature="serde")]
@@ -37,7 +43,18 @@ pub struct Source {
     /// Note that file names are optional and don't need to be provided at all.
     /// But it is strongly recommended to provide them for anything used as
     /// attachment but normally irrelevant for anything else.
-    pub use_file_name: Option<String>
+    pub use_file_name: Option<String>,
+
+    /// Allows specifying how the loaded data should be transfer encoded.
+    ///
+    /// E.g. `UseQuotedPrintable` for a generated CSV or `NoEncoding` for
+    /// a pre-wrapped us-ascii report. If `None` the hint the loader knows
+    /// for the data (e.g. the one of registered `Data`) is used, falling
+    /// back to `TransferEncodingHint::Auto`.
+    ///
+    /// The hint is also stored in the `Metadata` of the loaded data.
+    #[cfg_attr(feature="serde", serde(default))]
+    pub use_transfer_encoding: Option<TransferEncodingHint>
 }
 
 /// Specifies how the content type should be handled when loading the data.
@@ -47,6 +64,16 @@ pub struct Source {
 /// which can fail. Nevertheless how any of the aspects are handled in detail
 /// depends on the context implementation.
 ///
+/// # Precedence
+///
+/// Loaders are expected to use following precedence (see `resolve`):
+///
+/// 1. the media type given by `Override`
+/// 2. the media type the loader knows for the data (e.g. if it is stored
+///    alongside the data)
+/// 3. the media type given by `Default`
+/// 4. the media type detected by sniffing the data
+///
 #[derive(Debug, Clone)]
 #[cfg_attr(feature="serde", derive(Serialize, Deserialize))]
 pub enum UseMediaType {
@@ -60,8 +87,58 @@ pub enum UseMediaType {
     /// case that content type should be used.
     Default(MediaType),
 
-    // /// Always use this content type even if it is known to have a different content type.
-    // Override(MediaType)
+    /// Always use this content type even if it is known to have a different content type.
+    ///
+    /// E.g. to use `text/calendar; method=REQUEST` for a `.ics` file.
+    Override(MediaType)
+}
+
+impl UseMediaType {
+
+    /// Resolves which media type should be used.
+    ///
+    /// `known_media_type` is the media type known by the loader (if any),
+    /// `sniff` is only called if no media type is given through any
+    /// other way. See the precedence described in the type documentation.
+    pub fn resolve<F>(self, known_media_type: Option<MediaType>, sniff: F) -> MediaType
+        where F: FnOnce() -> MediaType
+    {
+        match self {
+            UseMediaType::Override(media_type) => media_type,
+            UseMediaType::Default(media_type) => known_media_type.unwrap_or(media_type),
+            UseMediaType::Auto => known_media_type.unwrap_or_else(sniff)
+        }
+    }
+}
+
+// compares/hashes the media types by their string representation
+impl PartialEq for UseMediaType {
+    fn eq(&self, other: &Self) -> bool {
+        use self::UseMediaType::*;
+        match (self, other) {
+            (&Auto, &Auto) => true,
+            (&Default(ref left), &Default(ref right)) |
+            (&Override(ref left), &Override(ref right)) => {
+                left.as_str_repr() == right.as_str_repr()
+            },
+            _ => false
+        }
+    }
+}
+
+impl Eq for UseMediaType {}
+
+impl Hash for UseMediaType {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        use self::UseMediaType::*;
+        mem::discriminant(self).hash(state);
+        match *self {
+            Auto => {},
+            Default(ref media_type) | Override(ref media_type) => {
+                media_type.as_str_repr().hash(state)
+            }
+        }
+    }
 }
 
 impl Default for UseMediaType {
@@ -69,3 +146,38 @@ impl Default for UseMediaType {
         UseMediaType::Auto
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn media_type(raw: &str) -> MediaType {
+        MediaType::parse(raw).unwrap()
+    }
+
+    fn resolve(use_media_type: UseMediaType, known: Option<&str>) -> String {
+        use_media_type
+            .resolve(known.map(media_type), || media_type("application/octet-stream"))
+            .as_str_repr()
+            .to_owned()
+    }
+
+    #[test]
+    fn override_has_the_highest_precedence() {
+        let use_media_type = UseMediaType::Override(media_type("text/calendar; method=REQUEST"));
+        assert_eq!(resolve(use_media_type, Some("text/plain")), "text/calendar; method=REQUEST");
+    }
+
+    #[test]
+    fn known_media_type_is_preferred_over_default() {
+        let use_media_type = UseMediaType::Default(media_type("text/html"));
+        assert_eq!(resolve(use_media_type.clone(), Some("text/plain")), "text/plain");
+        assert_eq!(resolve(use_media_type, None), "text/html");
+    }
+
+    #[test]
+    fn auto_only_sniffs_if_needed() {
+        assert_eq!(resolve(UseMediaType::Auto, Some("text/plain")), "text/plain");
+        assert_eq!(resolve(UseMediaType::Auto, None), "application/octet-stream");
+    }
+}
@@ -0,0 +1,255 @@
+//! Provides a `std::future::Future` based API on top of the futures 0.1 one.
+//!
+//! This module is only available with the `std-future` feature. It allows
+//! using this crate from `async`/`await` code without having to write
+//! compat shims everywhere:
+//!
+//! - `Mail::into_encodable_mail_async` returns a `std::future::Future`
+//!   resolving to the `EncodableMail`.
+//! - `StdFutureContext` provides `std::future::Future` based variants of the
+//!   asynchronous `Context` methods, it is implemented for every `Context`.
+//! - `StdFutureResourceLoaderComponent` is a `std::future::Future` based
+//!   variant of `ResourceLoaderComponent`, it can be used with a
+//!   `CompositeContext` by wrapping it in a `StdFutureLoader`.
+//!
+//! Note that this crate itself still uses the 2015 edition and as such can't
+//! provide `async fn`s, but all returned futures can be `.await`ed.
+use std::{
+    io,
+    fmt::Debug,
+    pin::Pin,
+    future::Future as StdFuture
+};
+
+use futures03::compat::{Compat, Future01CompatExt};
+
+use ::{
+    iri::IRI,
+    error::{MailError, ResourceLoadingError},
+    utils::SendBoxFuture,
+    resource::{Source, Data, EncData},
+    mail::{Mail, EncodableMail},
+    context::{Context, ResourceLoaderComponent}
+};
+
+/// Type alias for a boxed `std::future::Future` which is `Send`.
+pub type StdBoxFuture<I, E> = Pin<Box<StdFuture<Output=Result<I, E>> + Send + 'static>>;
+
+impl Mail {
+
+    /// Like `into_encodable_mail` but returns a `std::future::Future`.
+    ///
+    /// The future does not need to be polled from within a futures 0.1
+    /// task, so it can be `.await`ed on any (modern) async runtime.
+    ///
+    /// Use `into_encodable_mail_builder` if you need to set options like
+    /// `MailFutureBuilder::use_preloads`.
+    pub fn into_encodable_mail_async<C: Context>(self, ctx: C)
+        -> impl StdFuture<Output=Result<EncodableMail, MailError>>
+    {
+        self.into_encodable_mail(ctx).compat()
+    }
+}
+
+/// Provides `std::future::Future` based variants of the `Context` methods.
+///
+/// This is implemented for all `Context` implementations.
+pub trait StdFutureContext: Context {
+
+    /// Like `Context::load_resource` but returns a `std::future::Future`.
+    fn load_resource_std(&self, source: &Source)
+        -> StdBoxFuture<EncData, ResourceLoadingError>
+    {
+        Box::pin(self.load_resource(source).compat())
+    }
+
+    /// Like `Context::transfer_encode_resource` but returns a `std::future::Future`.
+    fn transfer_encode_resource_std(&self, data: &Data)
+        -> StdBoxFuture<EncData, ResourceLoadingError>
+    {
+        Box::pin(self.transfer_encode_resource(data).compat())
+    }
+
+    /// Like `Context::offload` but for a `std::future::Future`.
+    fn offload_std<F, I, E>(&self, fut: F) -> StdBoxFuture<I, E>
+        where F: StdFuture<Output=Result<I, E>> + Send + 'static,
+              I: Send + 'static,
+              E: From<io::Error> + Send + 'static
+    {
+        let fut = Compat::new(Box::pin(fut));
+        Box::pin(self.offload(fut).compat())
+    }
+}
+
+impl<C> StdFutureContext for C
+    where C: Context
+{}
+
+/// Like `ResourceLoaderComponent` but using `std::future::Future`.
+///
+/// Wrap it in a `StdFutureLoader` to use it as a `ResourceLoaderComponent`
+/// e.g. in a `CompositeContext`.
+pub trait StdFutureResourceLoaderComponent: Debug + Send + Sync + 'static {
+
+    /// See `ResourceLoaderComponent::load_resource`.
+    fn load_resource(&self, source: &Source, ctx: &impl Context)
+        -> StdBoxFuture<EncData, ResourceLoadingError>;
+
+    /// See `ResourceLoaderComponent::transfer_encode_resource`.
+    ///
+    /// The default implementation offloads transfer encoding the
+    /// data using it's transfer encoding hint.
+    fn transfer_encode_resource(&self, data: &Data, ctx: &impl Context)
+        -> StdBoxFuture<EncData, ResourceLoadingError>
+    {
+        let data = data.clone();
+        let fut = ctx.offload_fn(move || Ok(data.transfer_encode(data.transfer_encoding_hint())));
+        Box::pin(fut.compat())
+    }
+
+    /// See `ResourceLoaderComponent::use_preload`.
+    ///
+    /// The default implementation does nothing.
+    fn use_preload(&self, _preload: &IRI) {}
+
+    /// See `ResourceLoaderComponent::stop_using_preload`.
+    ///
+    /// The default implementation does nothing.
+    fn stop_using_preload(&self, _preload: &IRI) {}
+}
+
+/// Adapts a `StdFutureResourceLoaderComponent` to a `ResourceLoaderComponent`.
+///
+/// This is needed as `ResourceLoaderComponent` is implemented for all
+/// `Context` implementations, so it can't also be implemented for all
+/// `StdFutureResourceLoaderComponent` implementations.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StdFutureLoader<L> {
+    inner: L
+}
+
+impl<L> StdFutureLoader<L>
+    where L: StdFutureResourceLoaderComponent
+{
+    /// Wraps the given loader.
+    pub fn new(inner: L) -> Self {
+        StdFutureLoader { inner }
+    }
+
+    /// Returns a reference to the wrapped loader.
+    pub fn inner(&self) -> &L {
+        &self.inner
+    }
+
+    /// Returns the wrapped loader.
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+
+impl<L> ResourceLoaderComponent for StdFutureLoader<L>
+    where L: StdFutureResourceLoaderComponent
+{
+    fn load_resource(&self, source: &Source, ctx: &impl Context)
+        -> SendBoxFuture<EncData, ResourceLoadingError>
+    {
+        Box::new(Compat::new(self.inner.load_resource(source, ctx)))
+    }
+
+    fn transfer_encode_resource(&self, data: &Data, ctx: &impl Context)
+        -> SendBoxFuture<EncData, ResourceLoadingError>
+    {
+        Box::new(Compat::new(self.inner.transfer_encode_resource(data, ctx)))
+    }
+
+    fn use_preload(&self, preload: &IRI) {
+        self.inner.use_preload(preload)
+    }
+
+    fn stop_using_preload(&self, preload: &IRI) {
+        self.inner.stop_using_preload(preload)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures03::{executor::block_on, future};
+    use headers::{
+        headers::_From,
+        header_components::MediaType
+    };
+    use ::{
+        Resource,
+        context::CompositeContext,
+        default_impl::test_context,
+        resource::UseMediaType
+    };
+    use super::*;
+
+    fn source(iri: &str) -> Source {
+        Source {
+            iri: IRI::new(iri).unwrap(),
+            use_media_type: UseMediaType::Default(MediaType::parse("text/plain").unwrap()),
+            use_file_name: None,
+            use_transfer_encoding: None
+        }
+    }
+
+    /// Loads the tail of the IRI as `text/plain` data.
+    #[derive(Debug)]
+    struct TailLoader;
+
+    impl StdFutureResourceLoaderComponent for TailLoader {
+        fn load_resource(&self, source: &Source, ctx: &impl Context)
+            -> StdBoxFuture<EncData, ResourceLoadingError>
+        {
+            let data = Data::plain_text(source.iri.tail(), ctx.generate_content_id());
+            Box::pin(future::ready(Ok(data.transfer_encode(Default::default()))))
+        }
+    }
+
+    fn mail_with_source(iri: &str) -> Result<Mail, MailError> {
+        let mut mail = Mail::new_singlepart_mail(Resource::Source(source(iri)));
+        mail.insert_headers(headers! { _From: ["random@this.is.no.mail"] }?);
+        Ok(mail)
+    }
+
+    test!(into_encodable_mail_async_loads_resources, {
+        let ctx = test_context();
+        let data = Data::plain_text("hy there", ctx.generate_content_id());
+        ctx.resource_loader().register(IRI::new("mem:greeting").unwrap(), data);
+
+        let mail = mail_with_source("mem:greeting")?;
+        block_on(mail.into_encodable_mail_async(ctx))?;
+
+        let mail = mail_with_source("mem:missing")?;
+        match assert_err!(block_on(mail.into_encodable_mail_async(test_context()))) {
+            MailError::ResourceLoading(_) => {},
+            other => panic!("unexpected error: {:?}", other)
+        }
+    });
+
+    #[test]
+    fn context_methods_can_be_awaited() {
+        let ctx = test_context();
+        let err = assert_err!(block_on(ctx.load_resource_std(&source("mem:missing"))));
+        assert_eq!(err.source_iri().map(|iri| iri.as_str()), Some("mem:missing"));
+
+        let value: Result<u8, io::Error> = block_on(ctx.offload_std(future::ready(Ok(12))));
+        assert_eq!(assert_ok!(value), 12);
+    }
+
+    #[test]
+    fn std_future_loaders_can_be_used_as_component() {
+        let base = test_context();
+        let ctx = CompositeContext::new(
+            StdFutureLoader::new(TailLoader),
+            base.offloader().clone(),
+            base.id_gen().clone()
+        );
+
+        let enc_data = assert_ok!(block_on(ctx.load_resource_std(&source("std:hy"))));
+        let data = assert_ok!(enc_data.transfer_decode());
+        assert_eq!(&**data.buffer(), b"hy");
+    }
+}
@@ -33,6 +33,7 @@ fn loaded_resource(path: &str, media_type: &str, name: Option<&str>) -> EncData
         iri: IRI::from_parts("path", path).unwrap(),
         use_media_type: UseMediaType::Default(MediaType::parse(media_type).unwrap()),
         use_file_name: name.map(|s|s.to_owned()),
+        use_transfer_encoding: None
     };
 
     ctx.load_resource(&source).wait().unwrap()